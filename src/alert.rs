@@ -0,0 +1,251 @@
+use crate::calendar::format_utc_seconds;
+use std::process::{Command, Stdio};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertMetric {
+    Inbound,
+    Outbound,
+    Total,
+    Transit,
+    Internal,
+    Broadcast,
+    InboundPps,
+    OutboundPps,
+}
+
+impl AlertMetric {
+    pub const NAMES: [(&'static str, AlertMetric); 8] = [
+        ("in", AlertMetric::Inbound),
+        ("out", AlertMetric::Outbound),
+        ("total", AlertMetric::Total),
+        ("transit", AlertMetric::Transit),
+        ("internal", AlertMetric::Internal),
+        ("broadcast", AlertMetric::Broadcast),
+        ("in_pps", AlertMetric::InboundPps),
+        ("out_pps", AlertMetric::OutboundPps),
+    ];
+
+    pub fn name(&self) -> &'static str {
+        Self::NAMES
+            .iter()
+            .find(|(_, metric)| metric == self)
+            .map(|(name, _)| *name)
+            .unwrap_or_default()
+    }
+
+    pub fn is_packet_rate(&self) -> bool {
+        matches!(self, AlertMetric::InboundPps | AlertMetric::OutboundPps)
+    }
+
+    /// Unit of the values handed to the hook command.
+    pub fn unit(&self) -> &'static str {
+        if self.is_packet_rate() { "pps" } else { "bps" }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Above,
+    Below,
+}
+
+/// A rule such as `in > 800Mbps for 10s`. Rates are kept in bytes/s, packet
+/// rates in packets/s, like the rest of the calculator.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlertRule {
+    pub text: String,
+    pub metric: AlertMetric,
+    pub comparison: Comparison,
+    pub threshold: f64,
+    /// How long the condition (or its recovery) must hold before the state changes
+    pub duration: Duration,
+}
+
+impl AlertRule {
+    fn breached(&self, value: f64) -> bool {
+        match self.comparison {
+            Comparison::Above => value > self.threshold,
+            Comparison::Below => value < self.threshold,
+        }
+    }
+
+    // The value has to come back past the threshold by the hysteresis margin,
+    // so a rate hovering around the threshold doesn't fire and clear every interval
+    fn recovered(&self, value: f64, hysteresis: f64) -> bool {
+        match self.comparison {
+            Comparison::Above => value <= self.threshold * (1.0 - hysteresis),
+            Comparison::Below => value >= self.threshold * (1.0 + hysteresis),
+        }
+    }
+
+    /// Value in the unit passed to the hook: bit/s for rates, packets/s otherwise.
+    fn hook_value(&self, value: f64) -> f64 {
+        if self.metric.is_packet_rate() { value } else { value * 8.0 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertState {
+    Firing,
+    Cleared,
+}
+
+impl AlertState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AlertState::Firing => "firing",
+            AlertState::Cleared => "cleared",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlertEvent {
+    pub rule: String,
+    pub state: AlertState,
+    pub timestamp: SystemTime,
+    pub value: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AlertStatus {
+    /// Rules that are currently firing
+    pub firing: Vec<String>,
+    /// Rules that fired or cleared this interval
+    pub events: Vec<AlertEvent>,
+    pub hook_error: Option<String>,
+}
+
+struct RuleState {
+    rule: AlertRule,
+    firing: bool,
+    // When the condition that would change the state started holding
+    pending_since: Option<SystemTime>,
+}
+
+/// Evaluates the alert rules once per interval and runs the hook command
+/// whenever one of them fires or clears.
+pub struct AlertEngine {
+    rules: Vec<RuleState>,
+    hysteresis: f64,
+    command: Option<String>,
+    interface: String,
+    hook_error: Option<String>,
+}
+
+impl AlertEngine {
+    /// `hysteresis_percent` is how far back past the threshold a firing rule's value must go to clear.
+    pub fn new(rules: Vec<AlertRule>, hysteresis_percent: f64) -> Self {
+        Self {
+            rules: rules
+                .into_iter()
+                .map(|rule| RuleState { rule, firing: false, pending_since: None })
+                .collect(),
+            hysteresis: hysteresis_percent / 100.0,
+            command: None,
+            interface: String::new(),
+            hook_error: None,
+        }
+    }
+
+    /// Runs `command` with `sh -c` on every state change, with the details in `TCPGRAPH_ALERT_*` variables.
+    pub fn with_command(mut self, command: String, interface: String) -> Self {
+        self.command = Some(command);
+        self.interface = interface;
+        self
+    }
+
+    /// Checks every rule against the interval of length `interval` that ended at `timestamp`. A
+    /// condition holds from the start of the first interval it was seen in, so `for 10s` fires
+    /// at the end of the tenth 1 s interval.
+    pub fn evaluate(
+        &mut self,
+        timestamp: SystemTime,
+        interval: Duration,
+        value_of: impl Fn(AlertMetric) -> f64,
+    ) -> AlertStatus {
+        let mut events = Vec::new();
+
+        for state in &mut self.rules {
+            let value = value_of(state.rule.metric);
+            let changing = if state.firing {
+                state.rule.recovered(value, self.hysteresis)
+            } else {
+                state.rule.breached(value)
+            };
+            if !changing {
+                state.pending_since = None;
+                continue;
+            }
+
+            let since = *state.pending_since.get_or_insert(timestamp - interval);
+            if timestamp.duration_since(since).unwrap_or_default() < state.rule.duration {
+                continue;
+            }
+
+            state.firing = !state.firing;
+            state.pending_since = None;
+            events.push(AlertEvent {
+                rule: state.rule.text.clone(),
+                state: if state.firing { AlertState::Firing } else { AlertState::Cleared },
+                timestamp,
+                value,
+            });
+        }
+
+        for event in &events {
+            self.run_hook(event);
+        }
+
+        AlertStatus {
+            firing: self
+                .rules
+                .iter()
+                .filter(|state| state.firing)
+                .map(|state| state.rule.text.clone())
+                .collect(),
+            events,
+            hook_error: self.hook_error.clone(),
+        }
+    }
+
+    fn run_hook(&mut self, event: &AlertEvent) {
+        let Some(command) = &self.command else {
+            return;
+        };
+        let Some(rule) = self.rules.iter().map(|state| &state.rule).find(|rule| rule.text == event.rule) else {
+            return;
+        };
+
+        let result = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .env("TCPGRAPH_ALERT_RULE", &rule.text)
+            .env("TCPGRAPH_ALERT_STATE", event.state.name())
+            .env("TCPGRAPH_ALERT_METRIC", rule.metric.name())
+            .env("TCPGRAPH_ALERT_VALUE", format!("{:.0}", rule.hook_value(event.value)))
+            .env("TCPGRAPH_ALERT_THRESHOLD", format!("{:.0}", rule.hook_value(rule.threshold)))
+            .env("TCPGRAPH_ALERT_UNIT", rule.metric.unit())
+            .env("TCPGRAPH_ALERT_INTERFACE", &self.interface)
+            .env("TCPGRAPH_ALERT_TIME", format_utc_seconds(event.timestamp))
+            .env(
+                "TCPGRAPH_ALERT_TIMESTAMP",
+                event.timestamp.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs().to_string(),
+            )
+            // The terminal belongs to the UI
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+
+        match result {
+            Ok(mut child) => {
+                // Reap the hook in the background; a slow script must not hold up the next interval
+                std::thread::spawn(move || child.wait());
+                self.hook_error = None;
+            }
+            Err(e) => self.hook_error = Some(format!("Failed to run alert command: {}", e)),
+        }
+    }
+}
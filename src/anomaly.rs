@@ -0,0 +1,157 @@
+use crate::calendar::format_utc_seconds;
+use crate::capture::TrafficDirection;
+use anyhow::{Context, Result};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Fewer intervals than this are not enough to call anything unusual
+pub const MIN_BASELINE_SAMPLES: usize = 30;
+
+// Scales the median absolute deviation to a standard deviation for normally distributed traffic
+const MAD_TO_SIGMA: f64 = 1.4826;
+
+// Deviations below 10 kbit/s are never flagged, otherwise a mostly idle link
+// (where the spread is zero) would report every stray packet
+const MIN_SIGMA: f64 = 1250.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum BaselineMethod {
+    /// Mean and standard deviation
+    Mean,
+    /// Median and median absolute deviation, not skewed by earlier spikes
+    Mad,
+    /// No anomaly detection
+    Off,
+}
+
+impl BaselineMethod {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BaselineMethod::Mean => "mean/stddev",
+            BaselineMethod::Mad => "median/MAD",
+            BaselineMethod::Off => "off",
+        }
+    }
+
+    /// Typical rate and spread (as a standard deviation) of `samples`, in bytes/s.
+    pub fn baseline(&self, samples: &[f64]) -> Option<Baseline> {
+        if samples.is_empty() {
+            return None;
+        }
+
+        match self {
+            BaselineMethod::Off => None,
+            BaselineMethod::Mean => {
+                let count = samples.len() as f64;
+                let mean = samples.iter().sum::<f64>() / count;
+                let variance = samples.iter().map(|rate| (rate - mean).powi(2)).sum::<f64>() / count;
+                Some(Baseline { center: mean, sigma: variance.sqrt() })
+            }
+            BaselineMethod::Mad => {
+                let center = median(samples.to_vec());
+                let deviations = samples.iter().map(|rate| (rate - center).abs()).collect();
+                Some(Baseline { center, sigma: median(deviations) * MAD_TO_SIGMA })
+            }
+        }
+    }
+}
+
+fn median(mut values: Vec<f64>) -> f64 {
+    values.sort_by(|a, b| a.total_cmp(b));
+    let middle = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[middle - 1] + values[middle]) / 2.0
+    } else {
+        values[middle]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Baseline {
+    pub center: f64,
+    pub sigma: f64,
+}
+
+/// An interval whose rate was unusually far from the baseline.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Anomaly {
+    pub timestamp: SystemTime,
+    pub direction: TrafficDirection,
+    /// Unsmoothed rate of the interval, bytes/s
+    pub rate: f64,
+    /// Baseline rate it was compared against, bytes/s
+    pub baseline: f64,
+    /// Distance from the baseline in standard deviations, negative for drops
+    pub sigmas: f64,
+}
+
+impl Anomaly {
+    pub fn describe(&self) -> String {
+        format!(
+            "{} {} {:.2} Mbps vs baseline {:.2} Mbps ({:+.1}σ)",
+            format_utc_seconds(self.timestamp),
+            self.direction.name(),
+            self.rate * 8.0 / 1_000_000.0,
+            self.baseline * 8.0 / 1_000_000.0,
+            self.sigmas
+        )
+    }
+}
+
+/// Compares each interval's rate with a baseline learnt from the preceding ones
+/// and reports it when it is more than `threshold` standard deviations away.
+pub struct AnomalyDetector {
+    method: BaselineMethod,
+    threshold: f64,
+    log: Option<File>,
+}
+
+impl AnomalyDetector {
+    pub fn new(method: BaselineMethod, threshold: f64) -> Self {
+        Self { method, threshold, log: None }
+    }
+
+    /// Appends a line per anomaly to `path`.
+    pub fn with_log(mut self, path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open anomaly log {}", path.display()))?;
+        self.log = Some(file);
+        Ok(self)
+    }
+
+    pub fn check(
+        &mut self,
+        timestamp: SystemTime,
+        direction: TrafficDirection,
+        history: &[f64],
+        rate: f64,
+    ) -> Option<Anomaly> {
+        if history.len() < MIN_BASELINE_SAMPLES {
+            return None;
+        }
+
+        let baseline = self.method.baseline(history)?;
+        let sigmas = (rate - baseline.center) / baseline.sigma.max(MIN_SIGMA);
+        if sigmas.abs() <= self.threshold {
+            return None;
+        }
+
+        let anomaly = Anomaly {
+            timestamp,
+            direction,
+            rate,
+            baseline: baseline.center,
+            sigmas,
+        };
+        if let Some(log) = &mut self.log {
+            // A full disk shouldn't stop detection; the anomaly still shows in the UI
+            let _ = writeln!(log, "{}", anomaly.describe());
+        }
+        Some(anomaly)
+    }
+}
@@ -1,106 +1,1013 @@
-use crate::capture::{PacketInfo, TrafficDirection};
-use std::collections::VecDeque;
-use std::sync::mpsc;
-use std::time::{Duration, SystemTime};
+use crate::alert::{AlertEngine, AlertMetric, AlertStatus};
+use crate::anomaly::{Anomaly, AnomalyDetector};
+use crate::capture::{
+    CaptureDropCounts, CaptureDrops, ETHERNET_WIRE_OVERHEAD, FlowKey, MAX_SERIES, PacketCapture, PacketInfo, TrafficDirection,
+};
+use crate::counters::{CounterSampler, CounterSource, InterfaceCounters, KernelComparison};
+use crate::forecast::Forecast;
+use crate::fragment::{FragmentStats, FragmentTracker};
+use crate::quota::{QuotaStatus, QuotaTracker};
+use crate::tcp_window::WindowEvent;
+use anyhow::{Context, Result};
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex, PoisonError, mpsc};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
 
 #[derive(Debug, Clone)]
 pub struct BandwidthData {
     pub timestamp: SystemTime,
     pub inbound_bps: f64,
     pub outbound_bps: f64,
+    pub inbound_pps: f64,
+    pub outbound_pps: f64,
+    pub inbound_goodput_bps: f64,
+    pub outbound_goodput_bps: f64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TcpWindowStats {
+    pub zero_window_events: u64,
+    pub window_full_events: u64,
+    pub min_advertised_window: Option<u32>,
+}
+
+// Inclusive packet size ranges in bytes, chosen around minimum frames, common MTUs and jumbo frames
+pub const PACKET_SIZE_BUCKETS: [(u32, u32); 8] = [
+    (0, 64),
+    (65, 128),
+    (129, 256),
+    (257, 512),
+    (513, 1024),
+    (1025, 1500),
+    (1501, 9000),
+    (9001, u32::MAX),
+];
+
+#[derive(Debug, Clone, Default)]
+pub struct PacketSizeHistogram {
+    pub counts: [u64; PACKET_SIZE_BUCKETS.len()],
+}
+
+impl PacketSizeHistogram {
+    pub fn record(&mut self, size: u32) {
+        if let Some(index) = PACKET_SIZE_BUCKETS.iter().position(|(_, upper)| size <= *upper) {
+            self.counts[index] += 1;
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    pub fn bucket_label(index: usize) -> String {
+        match PACKET_SIZE_BUCKETS[index] {
+            (lower, u32::MAX) => format!(">{}", lower - 1),
+            (lower, upper) => format!("{}-{}", lower, upper),
+        }
+    }
+}
+
+/// Highest rate seen in any 1 ms / 10 ms slice of an update interval, in bytes/s
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BurstPeaks {
+    pub peak_1ms: f64,
+    pub peak_10ms: f64,
+}
+
+/// Bytes and packets moved in and out since `since`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransferTotals {
+    pub inbound_bytes: u64,
+    pub outbound_bytes: u64,
+    pub inbound_packets: u64,
+    pub outbound_packets: u64,
+    pub since: SystemTime,
+}
+
+impl TransferTotals {
+    pub fn new(since: SystemTime) -> Self {
+        Self {
+            inbound_bytes: 0,
+            outbound_bytes: 0,
+            inbound_packets: 0,
+            outbound_packets: 0,
+            since,
+        }
+    }
+
+    fn add(&mut self, packet: &PacketInfo) {
+        match packet.direction {
+            TrafficDirection::Inbound => {
+                self.inbound_bytes += packet.size as u64;
+                self.inbound_packets += 1;
+            }
+            TrafficDirection::Outbound => {
+                self.outbound_bytes += packet.size as u64;
+                self.outbound_packets += 1;
+            }
+            _ => {}
+        }
+    }
+
+    fn add_counters(&mut self, counters: &InterfaceCounters) {
+        self.inbound_bytes += counters.rx_bytes;
+        self.inbound_packets += counters.rx_packets;
+        self.outbound_bytes += counters.tx_bytes;
+        self.outbound_packets += counters.tx_packets;
+    }
+}
+
+/// Spans of the load-average style rates reported next to the instantaneous one. Each is
+/// rounded to whole update intervals; those shorter than one interval are left out.
+pub const AVERAGE_WINDOWS: [Duration; 3] = [
+    Duration::from_secs(10),
+    Duration::from_secs(60),
+    Duration::from_secs(300),
+];
+
+/// Mean rate over one of the `AVERAGE_WINDOWS`, in bytes/s.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WindowAverage {
+    /// The span actually averaged, a whole number of update intervals
+    pub window: Duration,
+    pub inbound: f64,
+    pub outbound: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct RollingSum {
+    window: Duration,
+    intervals: usize,
+    inbound_bytes: u64,
+    outbound_bytes: u64,
+    span: Duration,
+}
+
+// Bytes of the most recent intervals, with a running sum per averaging window so
+// that updating all of them costs the same no matter how short the interval is
+struct WindowAverages {
+    samples: VecDeque<(u64, u64, Duration)>,
+    sums: Vec<RollingSum>,
+}
+
+impl WindowAverages {
+    fn new(update_interval: Duration) -> Self {
+        let sums = AVERAGE_WINDOWS
+            .iter()
+            .filter(|window| **window >= update_interval)
+            .map(|window| {
+                let intervals = (window.as_secs_f64() / update_interval.as_secs_f64()).round() as usize;
+                RollingSum { window: update_interval * intervals as u32, intervals, ..RollingSum::default() }
+            })
+            .collect();
+        Self { samples: VecDeque::new(), sums }
+    }
+
+    fn push(&mut self, inbound_bytes: u64, outbound_bytes: u64, span: Duration) {
+        self.samples.push_back((inbound_bytes, outbound_bytes, span));
+        for sum in &mut self.sums {
+            sum.inbound_bytes += inbound_bytes;
+            sum.outbound_bytes += outbound_bytes;
+            sum.span += span;
+            // Drop the interval that just slid out of this window
+            if let Some(index) = self.samples.len().checked_sub(sum.intervals + 1) {
+                let (inbound, outbound, span) = self.samples[index];
+                sum.inbound_bytes -= inbound;
+                sum.outbound_bytes -= outbound;
+                sum.span -= span;
+            }
+        }
+
+        let longest = self.sums.iter().map(|sum| sum.intervals).max().unwrap_or(1);
+        while self.samples.len() > longest {
+            self.samples.pop_front();
+        }
+    }
+
+    fn averages(&self) -> Vec<WindowAverage> {
+        self.sums
+            .iter()
+            .map(|sum| {
+                // Until a window has filled up, this is the time seen so far
+                let seconds = sum.span.as_secs_f64();
+                let rate = |bytes: u64| if seconds > 0.0 { bytes as f64 / seconds } else { 0.0 };
+                WindowAverage {
+                    window: sum.window,
+                    inbound: rate(sum.inbound_bytes),
+                    outbound: rate(sum.outbound_bytes),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Requests from the UI to the bandwidth monitor task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MonitorCommand {
+    /// Restart the "since reset" totals at the time the key was pressed
+    ResetTotals(SystemTime),
+}
+
+// Relative width of a rate bucket
+const RATE_BUCKET_GROWTH: f64 = 1.01;
+
+/// Worst-case error of a reported percentile, in percent: each bucket reports its
+/// geometric middle, at most sqrt(RATE_BUCKET_GROWTH) - 1 away from any rate in it.
+pub const PERCENTILE_ERROR_PERCENT: f64 = 0.5;
+
+/// Distribution of per-interval rates over the session, kept in log-spaced buckets
+/// so memory stays bounded however long (or however finely sampled) the session is.
+#[derive(Debug, Clone, Default)]
+pub struct RateDistribution {
+    buckets: BTreeMap<u32, u64>,
+    count: u64,
+    sum: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RateSummary {
+    pub samples: u64,
+    pub mean: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p95: f64,
+    pub p99: f64,
+}
+
+impl RateDistribution {
+    pub fn record(&mut self, rate: f64) {
+        // Bucket 0 holds idle intervals (below 1 byte/s), bucket k covers [g^(k-1), g^k)
+        let bucket = if rate < 1.0 {
+            0
+        } else {
+            rate.log(RATE_BUCKET_GROWTH).floor() as u32 + 1
+        };
+        *self.buckets.entry(bucket).or_default() += 1;
+        self.count += 1;
+        self.sum += rate;
+    }
+
+    /// Nearest-rank percentiles, as used for 95th-percentile billing: the p95 is the
+    /// highest rate left once the busiest 5% of intervals are discarded.
+    pub fn summary(&self) -> RateSummary {
+        if self.count == 0 {
+            return RateSummary::default();
+        }
+
+        let ranks = [50.0, 90.0, 95.0, 99.0].map(|p: f64| ((p / 100.0 * self.count as f64).ceil() as u64).max(1));
+        let mut values = [0.0; 4];
+        let mut seen = 0;
+        let mut next = 0;
+        for (bucket, count) in &self.buckets {
+            seen += count;
+            while next < ranks.len() && ranks[next] <= seen {
+                values[next] = Self::bucket_value(*bucket);
+                next += 1;
+            }
+        }
+
+        RateSummary {
+            samples: self.count,
+            mean: self.sum / self.count as f64,
+            p50: values[0],
+            p90: values[1],
+            p95: values[2],
+            p99: values[3],
+        }
+    }
+
+    // Geometric middle of the bucket
+    fn bucket_value(bucket: u32) -> f64 {
+        if bucket == 0 {
+            0.0
+        } else {
+            RATE_BUCKET_GROWTH.powf(bucket as f64 - 0.5)
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct DirectionalBandwidth {
+    /// When the interval these rates cover was closed
+    pub timestamp: SystemTime,
     pub inbound: f64,
     pub outbound: f64,
+    pub raw_inbound: f64,
+    pub raw_outbound: f64,
+    pub inbound_pps: f64,
+    pub outbound_pps: f64,
+    pub inbound_goodput: f64,
+    pub outbound_goodput: f64,
+    /// Rates with Ethernet framing, preamble and gap whatever the accounting layer, for link utilization
+    pub inbound_wire: f64,
+    pub outbound_wire: f64,
+    pub transit: f64,
+    pub internal: f64,
+    pub broadcast: f64,
+    /// Sum of the rates of the directions selected with `with_total`
+    pub total: f64,
+    /// Rate of each `--series`, in the order they were given
+    pub series: Vec<f64>,
+    /// Unsmoothed inbound/outbound rates over each of the `AVERAGE_WINDOWS`
+    pub averages: Vec<WindowAverage>,
+    /// Trend of the bandwidth history, once `with_forecast` is on and there is enough of it
+    pub inbound_forecast: Option<Forecast>,
+    pub outbound_forecast: Option<Forecast>,
+    pub inbound_burst: BurstPeaks,
+    pub outbound_burst: BurstPeaks,
+    /// Session statistics of the unsmoothed per-interval rates
+    pub inbound_summary: RateSummary,
+    pub outbound_summary: RateSummary,
+    pub session_totals: TransferTotals,
+    pub totals_since_reset: TransferTotals,
+    pub quota: Option<QuotaStatus>,
+    /// Directions whose rate this interval stood out from the recent baseline
+    pub anomalies: Vec<Anomaly>,
+    pub alerts: Option<AlertStatus>,
+    /// The interface's kernel counters next to the capture, from the second interval on
+    pub kernel: Option<KernelComparison>,
+    /// Packets pcap dropped since start, when capturing
+    pub capture_drops: Option<CaptureDropCounts>,
+    pub tcp_windows: TcpWindowStats,
+    pub active_flows: usize,
+    pub fragments: FragmentStats,
+    pub size_histogram: PacketSizeHistogram,
+    pub session_size_histogram: PacketSizeHistogram,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SmoothingMode {
+    /// Simple moving average over the last N samples
+    Sma,
+    /// Exponentially weighted moving average
+    Ewma,
+    /// Rolling median over the last N samples, ignores single-sample outliers
+    Median,
+    /// Raw per-interval rates
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Smoothing {
+    None,
+    MovingAverage(usize),
+    Median(usize),
+    /// Weight of the newest sample, between 0 and 1
+    Ewma(f64),
+}
+
+impl Smoothing {
+    // Alpha that halves a sample's weight after `half_life` intervals
+    pub fn ewma_from_half_life(half_life: f64) -> Self {
+        Smoothing::Ewma(1.0 - 0.5f64.powf(1.0 / half_life))
+    }
+
+    fn samples(&self) -> usize {
+        match self {
+            Smoothing::MovingAverage(samples) | Smoothing::Median(samples) => (*samples).max(1),
+            Smoothing::None | Smoothing::Ewma(_) => 1,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Smoothing::None => "none".to_string(),
+            Smoothing::MovingAverage(samples) => format!("moving average of {} samples", samples),
+            Smoothing::Median(samples) => format!("median of {} samples", samples),
+            Smoothing::Ewma(alpha) => format!("EWMA, alpha {:.3}", alpha),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Rates {
+    inbound: f64,
+    outbound: f64,
+    inbound_pps: f64,
+    outbound_pps: f64,
+    inbound_goodput: f64,
+    outbound_goodput: f64,
+    inbound_wire: f64,
+    outbound_wire: f64,
+    transit: f64,
+    internal: f64,
+    broadcast: f64,
+    series: [f64; MAX_SERIES],
+}
+
+impl Rates {
+    fn bytes(&self, direction: TrafficDirection) -> f64 {
+        match direction {
+            TrafficDirection::Inbound => self.inbound,
+            TrafficDirection::Outbound => self.outbound,
+            TrafficDirection::Transit => self.transit,
+            TrafficDirection::Internal => self.internal,
+            TrafficDirection::Broadcast => self.broadcast,
+        }
+    }
+}
+
+// Cap on distinct flows remembered per interval so a port scan can't grow the set without bound
+const MAX_FLOWS_PER_INTERVAL: usize = 65536;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct DirectionTotals {
+    bytes: u64,
+    goodput_bytes: u64,
+    wire_bytes: u64,
+    packets: u64,
+}
+
+impl DirectionTotals {
+    fn add(&mut self, packet: &PacketInfo) {
+        self.bytes += packet.size as u64;
+        self.goodput_bytes += packet.payload_size as u64;
+        self.wire_bytes += PacketCapture::wire_size(packet.frame_size) as u64;
+        self.packets += 1;
+    }
+
+    fn merge(&mut self, other: &DirectionTotals) {
+        self.bytes += other.bytes;
+        self.goodput_bytes += other.goodput_bytes;
+        self.wire_bytes += other.wire_bytes;
+        self.packets += other.packets;
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct IntervalTotals {
+    inbound: DirectionTotals,
+    outbound: DirectionTotals,
+    transit: DirectionTotals,
+    internal: DirectionTotals,
+    broadcast: DirectionTotals,
+    // Bytes matching each series, whatever their direction
+    series: [u64; MAX_SERIES],
+    // Time the bucket was actually open, which drifts from the update interval under load
+    span: Duration,
+}
+
+impl IntervalTotals {
+    fn direction_mut(&mut self, direction: TrafficDirection) -> &mut DirectionTotals {
+        match direction {
+            TrafficDirection::Inbound => &mut self.inbound,
+            TrafficDirection::Outbound => &mut self.outbound,
+            TrafficDirection::Transit => &mut self.transit,
+            TrafficDirection::Internal => &mut self.internal,
+            TrafficDirection::Broadcast => &mut self.broadcast,
+        }
+    }
+
+    fn merge(&mut self, other: &IntervalTotals) {
+        self.inbound.merge(&other.inbound);
+        self.outbound.merge(&other.outbound);
+        self.transit.merge(&other.transit);
+        self.internal.merge(&other.internal);
+        self.broadcast.merge(&other.broadcast);
+        for (bytes, other) in self.series.iter_mut().zip(other.series) {
+            *bytes += other;
+        }
+        self.span += other.span;
+    }
+}
+
+// Bytes counted in the current time slot of a fixed width, and the fullest slot so far
+#[derive(Debug, Clone, Copy, Default)]
+struct BurstSlot {
+    index: u64,
+    bytes: u64,
+    peak: u64,
+}
+
+impl BurstSlot {
+    fn add(&mut self, index: u64, bytes: u64) {
+        if index != self.index {
+            self.peak = self.peak.max(self.bytes);
+            self.index = index;
+            self.bytes = 0;
+        }
+        self.bytes += bytes;
+    }
+
+    fn peak(&self) -> u64 {
+        self.peak.max(self.bytes)
+    }
+}
+
+// Slots are aligned to capture timestamps, so packets must arrive in timestamp order (as pcap delivers them)
+#[derive(Debug, Clone, Copy, Default)]
+struct BurstMeter {
+    one_ms: BurstSlot,
+    ten_ms: BurstSlot,
+}
+
+impl BurstMeter {
+    fn add(&mut self, timestamp: SystemTime, bytes: u64) {
+        let micros = timestamp.duration_since(UNIX_EPOCH).unwrap_or_default().as_micros() as u64;
+        self.one_ms.add(micros / 1_000, bytes);
+        self.ten_ms.add(micros / 10_000, bytes);
+    }
+
+    fn peaks(&self) -> BurstPeaks {
+        BurstPeaks {
+            peak_1ms: self.one_ms.peak() as f64 * 1_000.0,
+            peak_10ms: self.ten_ms.peak() as f64 * 100.0,
+        }
+    }
+}
+
+// Everything observed during one update interval, accumulated packet by packet
+#[derive(Debug, Default)]
+struct IntervalBucket {
+    totals: IntervalTotals,
+    inbound_burst: BurstMeter,
+    outbound_burst: BurstMeter,
+    tcp_windows: TcpWindowStats,
+    flows: HashSet<FlowKey>,
+    fragments: FragmentStats,
+    size_histogram: PacketSizeHistogram,
+}
+
+impl IntervalBucket {
+    fn add(&mut self, packet: &PacketInfo) {
+        self.totals.direction_mut(packet.direction).add(packet);
+        for (index, bytes) in self.totals.series.iter_mut().enumerate() {
+            if packet.series & (1 << index) != 0 {
+                *bytes += packet.size as u64;
+            }
+        }
+        match packet.direction {
+            TrafficDirection::Inbound => self.inbound_burst.add(packet.timestamp, packet.size as u64),
+            TrafficDirection::Outbound => self.outbound_burst.add(packet.timestamp, packet.size as u64),
+            _ => {}
+        }
+
+        if let Some(window) = packet.tcp_window {
+            match window.event {
+                Some(WindowEvent::ZeroWindow) => self.tcp_windows.zero_window_events += 1,
+                Some(WindowEvent::WindowFull) => self.tcp_windows.window_full_events += 1,
+                None => {}
+            }
+            if let Some(advertised) = window.advertised_window {
+                self.tcp_windows.min_advertised_window = Some(
+                    self.tcp_windows.min_advertised_window
+                        .map_or(advertised, |min| min.min(advertised)),
+                );
+            }
+        }
+
+        if let Some(flow) = packet.flow
+            && self.flows.len() < MAX_FLOWS_PER_INTERVAL
+        {
+            self.flows.insert(flow);
+        }
+
+        self.fragments.fragmented_datagrams += packet.fragments.fragmented_datagrams;
+        self.fragments.reassembly_timeouts += packet.fragments.reassembly_timeouts;
+        self.fragments.overflow_drops += packet.fragments.overflow_drops;
+        self.size_histogram.record(packet.frame_size);
+    }
 }
 
 pub struct BandwidthCalculator {
-    packet_buffer: VecDeque<PacketInfo>,
+    current_bucket: IntervalBucket,
+    bucket_started: Instant,
+    measured_spans: bool,
+    recent_intervals: VecDeque<IntervalTotals>,
+    window_averages: WindowAverages,
+    session_size_histogram: PacketSizeHistogram,
+    inbound_rates: RateDistribution,
+    outbound_rates: RateDistribution,
+    session_totals: TransferTotals,
+    totals_since_reset: TransferTotals,
     bandwidth_history: VecDeque<BandwidthData>,
-    smoothing_buffer: VecDeque<DirectionalBandwidth>,
+    smoothing_buffer: VecDeque<Rates>,
+    last_smoothed: Option<Rates>,
     max_history: usize,
     window_duration: Duration,
-    smoothing_samples: usize,
+    update_interval: Duration,
+    smoothing: Smoothing,
+    total_directions: Vec<TrafficDirection>,
+    quota: Option<QuotaTracker>,
+    anomaly_detector: Option<AnomalyDetector>,
+    alerts: Option<AlertEngine>,
+    series_count: usize,
+    forecast: bool,
+    kernel_counters: Option<CounterSampler>,
+    counter_source: Option<CounterSource>,
+    fragment_tracker: Option<Arc<Mutex<FragmentTracker>>>,
+    capture_drops: Option<Arc<CaptureDrops>>,
+    // Every packet added, whatever its direction, to compare with the kernel's counters
+    captured_packets: u64,
+}
+
+impl DirectionalBandwidth {
+    // Share of the counted bytes that is protocol headers rather than application data
+    pub fn overhead_percent(&self) -> f64 {
+        let throughput = self.inbound + self.outbound;
+        if throughput <= 0.0 {
+            return 0.0;
+        }
+        let goodput = (self.inbound_goodput + self.outbound_goodput).min(throughput);
+        (1.0 - goodput / throughput) * 100.0
+    }
 }
 
 impl BandwidthCalculator {
+    /// Each call to `calculate_bandwidth` closes the current bucket. Rates are averaged
+    /// over as many recent buckets as fit in `window_duration`; by default the update
+    /// interval is assumed to equal the window, see `with_update_interval`.
     pub fn new(window_duration: Duration, max_history: usize, smoothing_samples: usize) -> Self {
+        let start = SystemTime::now();
         Self {
-            packet_buffer: VecDeque::new(),
+            current_bucket: IntervalBucket::default(),
+            bucket_started: Instant::now(),
+            measured_spans: false,
+            recent_intervals: VecDeque::new(),
+            window_averages: WindowAverages::new(window_duration),
+            session_size_histogram: PacketSizeHistogram::default(),
+            inbound_rates: RateDistribution::default(),
+            outbound_rates: RateDistribution::default(),
+            session_totals: TransferTotals::new(start),
+            totals_since_reset: TransferTotals::new(start),
             bandwidth_history: VecDeque::new(),
             smoothing_buffer: VecDeque::new(),
+            last_smoothed: None,
             max_history,
             window_duration,
-            smoothing_samples,
+            update_interval: window_duration,
+            smoothing: Smoothing::MovingAverage(smoothing_samples),
+            total_directions: vec![TrafficDirection::Inbound, TrafficDirection::Outbound],
+            quota: None,
+            anomaly_detector: None,
+            alerts: None,
+            series_count: 0,
+            forecast: false,
+            kernel_counters: None,
+            counter_source: None,
+            fragment_tracker: None,
+            capture_drops: None,
+            captured_packets: 0,
+        }
+    }
+
+    /// Selects which directions add up to `DirectionalBandwidth::total` (inbound and outbound by default).
+    pub fn with_total(mut self, directions: Vec<TrafficDirection>) -> Self {
+        self.total_directions.clear();
+        for direction in directions {
+            if !self.total_directions.contains(&direction) {
+                self.total_directions.push(direction);
+            }
         }
+        self
+    }
+
+    pub fn with_smoothing(mut self, smoothing: Smoothing) -> Self {
+        self.smoothing = smoothing;
+        self
+    }
+
+    pub fn with_update_interval(mut self, update_interval: Duration) -> Self {
+        self.update_interval = update_interval;
+        self.window_averages = WindowAverages::new(update_interval);
+        self
+    }
+
+    /// Ends each bucket when `calculate_bandwidth` is called rather than one update interval
+    /// after the previous one, for a live monitor whose ticks can run late.
+    pub fn with_measured_spans(mut self) -> Self {
+        self.measured_spans = true;
+        self
+    }
+
+    fn window_intervals(&self) -> usize {
+        let intervals = self.window_duration.as_secs_f64() / self.update_interval.as_secs_f64();
+        (intervals.round() as usize).max(1)
     }
 
     pub fn add_packet(&mut self, packet: PacketInfo) {
-        self.packet_buffer.push_back(packet);
-        self.cleanup_old_packets();
+        self.session_totals.add(&packet);
+        self.totals_since_reset.add(&packet);
+        self.session_size_histogram.record(packet.frame_size);
+        self.captured_packets += 1;
+        self.current_bucket.add(&packet);
+    }
+
+    pub fn with_quota(mut self, quota: QuotaTracker) -> Self {
+        self.quota = Some(quota);
+        self
     }
 
+    /// Checks every interval against a baseline learnt from the bandwidth history.
+    pub fn with_anomaly_detector(mut self, detector: AnomalyDetector) -> Self {
+        self.anomaly_detector = Some(detector);
+        self
+    }
+
+    /// Reports a rate for each of the first `count` series tagged by the capture.
+    pub fn with_series(mut self, count: usize) -> Self {
+        self.series_count = count.min(MAX_SERIES);
+        self
+    }
+
+    /// Fits a trend to the bandwidth history after every interval.
+    pub fn with_forecast(mut self) -> Self {
+        self.forecast = true;
+        self
+    }
+
+    pub fn with_alerts(mut self, alerts: AlertEngine) -> Self {
+        self.alerts = Some(alerts);
+        self
+    }
+
+    /// Compares the captured traffic with the interface counters every interval.
+    pub fn with_kernel_counters(mut self, sampler: CounterSampler) -> Self {
+        self.kernel_counters = Some(sampler);
+        self
+    }
+
+    /// Polls the interface counters at the end of every interval, for running without a capture.
+    pub fn with_counter_source(mut self, source: CounterSource) -> Self {
+        self.counter_source = Some(source);
+        self
+    }
+
+    /// Expires the capture's incomplete datagrams at the end of every interval, so their
+    /// timeouts show up even when no more packets arrive.
+    pub fn with_fragment_tracker(mut self, tracker: Arc<Mutex<FragmentTracker>>) -> Self {
+        self.fragment_tracker = Some(tracker);
+        self
+    }
+
+    /// Reports pcap's drop counters with every interval.
+    pub fn with_capture_drops(mut self, drops: Arc<CaptureDrops>) -> Self {
+        self.capture_drops = Some(drops);
+        self
+    }
+
+    /// Counts received traffic as inbound and sent traffic as outbound. There is no
+    /// payload or per-packet detail, so goodput, bursts and packet sizes stay empty.
+    pub fn add_counters(&mut self, counters: &InterfaceCounters) {
+        self.session_totals.add_counters(counters);
+        self.totals_since_reset.add_counters(counters);
+        let totals = &mut self.current_bucket.totals;
+        totals.inbound.bytes += counters.rx_bytes;
+        totals.inbound.packets += counters.rx_packets;
+        totals.outbound.bytes += counters.tx_bytes;
+        totals.outbound.packets += counters.tx_packets;
+        // Without the frames the padding of short ones is unknown, so this is a slight underestimate
+        let overhead = ETHERNET_WIRE_OVERHEAD as u64;
+        totals.inbound.wire_bytes += counters.rx_bytes + counters.rx_packets * overhead;
+        totals.outbound.wire_bytes += counters.tx_bytes + counters.tx_packets * overhead;
+    }
+
+    /// Starts the "since reset" totals over at `at`; the session totals keep counting.
+    pub fn reset_totals(&mut self, at: SystemTime) {
+        self.totals_since_reset = TransferTotals::new(at);
+    }
+
+    /// Takes everything queued since the previous interval. A reset applies between the
+    /// packets captured before and after it, not to everything that was still queued.
+    pub fn drain(&mut self, command_rx: &mpsc::Receiver<MonitorCommand>, packet_rx: &mpsc::Receiver<PacketInfo>) {
+        let mut resets: Vec<SystemTime> = command_rx
+            .try_iter()
+            .map(|command| match command {
+                MonitorCommand::ResetTotals(at) => at,
+            })
+            .collect();
+        resets.sort();
+        let mut resets = resets.into_iter().peekable();
+
+        for packet in packet_rx.try_iter() {
+            while let Some(at) = resets.next_if(|at| *at <= packet.timestamp) {
+                self.reset_totals(at);
+            }
+            self.add_packet(packet);
+        }
+        for at in resets {
+            self.reset_totals(at);
+        }
+    }
+
+    /// Counts the unfinished bucket towards the quota and writes the quota state, for shutdown.
+    pub fn finish(&mut self) -> Result<()> {
+        let bucket = std::mem::take(&mut self.current_bucket);
+        match &mut self.quota {
+            Some(quota) => {
+                quota.add(bucket.totals.inbound.bytes, bucket.totals.outbound.bytes, SystemTime::now());
+                quota.save().context("Failed to save the quota state")
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Closes the bucket at the current time, see `with_measured_spans`; otherwise as if
+    /// exactly one update interval had passed since the previous one.
     pub fn calculate_bandwidth(&mut self) -> DirectionalBandwidth {
+        let end = if self.measured_spans { Instant::now() } else { self.bucket_started + self.update_interval };
+        self.calculate_bandwidth_at(end)
+    }
+
+    /// Closes the bucket at `end`; rates divide by the time the buckets were actually open.
+    pub fn calculate_bandwidth_at(&mut self, end: Instant) -> DirectionalBandwidth {
         let now = SystemTime::now();
-        let cutoff_time = now - self.window_duration;
+        if let Some(counters) = self.counter_source.as_mut().and_then(CounterSource::poll) {
+            self.add_counters(&counters);
+        }
+        if let Some(tracker) = &self.fragment_tracker {
+            let timeouts = tracker.lock().unwrap_or_else(PoisonError::into_inner).expire(now);
+            self.current_bucket.fragments.reassembly_timeouts += timeouts;
+        }
+        let mut bucket = std::mem::take(&mut self.current_bucket);
+        bucket.totals.span = end.saturating_duration_since(self.bucket_started);
+        self.bucket_started = end;
 
-        let (inbound_bytes, outbound_bytes): (u64, u64) = self.packet_buffer
-            .iter()
-            .filter(|packet| packet.timestamp >= cutoff_time)
-            .fold((0, 0), |(in_acc, out_acc), packet| {
-                match packet.direction {
-                    TrafficDirection::Inbound => (in_acc + packet.size as u64, out_acc),
-                    TrafficDirection::Outbound => (in_acc, out_acc + packet.size as u64),
-                    TrafficDirection::Unknown => {
-                        // For router scenarios, unknown traffic (neither source nor dest MAC is ours)
-                        // represents forwarded traffic. We'll count it as transit traffic.
-                        // For now, we'll split it to show total network activity.
-                        let half_size = packet.size as u64 / 2;
-                        (in_acc + half_size, out_acc + half_size)
-                    }
-                }
-            });
+        if let Some(quota) = &mut self.quota {
+            quota.add(bucket.totals.inbound.bytes, bucket.totals.outbound.bytes, now);
+        }
 
-        let inbound_bps = inbound_bytes as f64 / self.window_duration.as_secs_f64();
-        let outbound_bps = outbound_bytes as f64 / self.window_duration.as_secs_f64();
+        self.window_averages.push(bucket.totals.inbound.bytes, bucket.totals.outbound.bytes, bucket.totals.span);
+
+        // Percentiles are of the interval that just closed, not of the overlapping window averages
+        let interval_secs = bucket.totals.span.as_secs_f64();
+        let interval_rate = |count: u64| if interval_secs > 0.0 { count as f64 / interval_secs } else { 0.0 };
+        self.inbound_rates.record(interval_rate(bucket.totals.inbound.bytes));
+        self.outbound_rates.record(interval_rate(bucket.totals.outbound.bytes));
+        self.recent_intervals.push_back(bucket.totals);
+        while self.recent_intervals.len() > self.window_intervals() {
+            self.recent_intervals.pop_front();
+        }
+        let window = self.recent_intervals.iter().fold(IntervalTotals::default(), |mut acc, totals| {
+            acc.merge(totals);
+            acc
+        });
+        let window_secs = window.span.as_secs_f64();
+
+        let rate = |count: u64| if window_secs > 0.0 { count as f64 / window_secs } else { 0.0 };
+        let inbound_bps = rate(window.inbound.bytes);
+        let outbound_bps = rate(window.outbound.bytes);
+        let inbound_pps = rate(window.inbound.packets);
+        let outbound_pps = rate(window.outbound.packets);
+        let inbound_goodput_bps = rate(window.inbound.goodput_bytes);
+        let outbound_goodput_bps = rate(window.outbound.goodput_bytes);
 
         let bandwidth_data = BandwidthData {
             timestamp: now,
             inbound_bps,
             outbound_bps,
+            inbound_pps,
+            outbound_pps,
+            inbound_goodput_bps,
+            outbound_goodput_bps,
         };
 
+        // The baseline is the history before this interval, so a spike can't hide itself
+        let mut anomalies = Vec::new();
+        if let Some(detector) = &mut self.anomaly_detector {
+            let inbound_history: Vec<f64> = self.bandwidth_history.iter().map(|data| data.inbound_bps).collect();
+            let outbound_history: Vec<f64> = self.bandwidth_history.iter().map(|data| data.outbound_bps).collect();
+            anomalies.extend(detector.check(now, TrafficDirection::Inbound, &inbound_history, inbound_bps));
+            anomalies.extend(detector.check(now, TrafficDirection::Outbound, &outbound_history, outbound_bps));
+        }
+
         self.bandwidth_history.push_back(bandwidth_data);
 
         if self.bandwidth_history.len() > self.max_history {
             self.bandwidth_history.pop_front();
         }
 
-        let raw_bandwidth = DirectionalBandwidth {
+        let (inbound_forecast, outbound_forecast) = if self.forecast {
+            (
+                Forecast::fit(self.bandwidth_history.iter().map(|data| data.inbound_bps)),
+                Forecast::fit(self.bandwidth_history.iter().map(|data| data.outbound_bps)),
+            )
+        } else {
+            (None, None)
+        };
+
+        let tcp_windows = bucket.tcp_windows;
+        let active_flows = bucket.flows.len();
+        let fragments = bucket.fragments;
+        let size_histogram = bucket.size_histogram;
+
+        let raw = Rates {
             inbound: inbound_bps,
             outbound: outbound_bps,
+            inbound_pps,
+            outbound_pps,
+            inbound_goodput: inbound_goodput_bps,
+            outbound_goodput: outbound_goodput_bps,
+            inbound_wire: rate(window.inbound.wire_bytes),
+            outbound_wire: rate(window.outbound.wire_bytes),
+            transit: rate(window.transit.bytes),
+            internal: rate(window.internal.bytes),
+            broadcast: rate(window.broadcast.bytes),
+            series: window.series.map(rate),
         };
 
         // Add to smoothing buffer
-        self.smoothing_buffer.push_back(raw_bandwidth.clone());
-        if self.smoothing_buffer.len() > self.smoothing_samples {
+        self.smoothing_buffer.push_back(raw);
+        if self.smoothing_buffer.len() > self.smoothing.samples() {
             self.smoothing_buffer.pop_front();
         }
 
-        // Calculate smoothed bandwidth
-        let smoothed_inbound = self.smoothing_buffer.iter()
-            .map(|b| b.inbound)
-            .sum::<f64>() / self.smoothing_buffer.len() as f64;
-        
-        let smoothed_outbound = self.smoothing_buffer.iter()
-            .map(|b| b.outbound)
-            .sum::<f64>() / self.smoothing_buffer.len() as f64;
+        let smoothed = Rates {
+            inbound: self.smooth(|r| r.inbound),
+            outbound: self.smooth(|r| r.outbound),
+            inbound_pps: self.smooth(|r| r.inbound_pps),
+            outbound_pps: self.smooth(|r| r.outbound_pps),
+            inbound_goodput: self.smooth(|r| r.inbound_goodput),
+            outbound_goodput: self.smooth(|r| r.outbound_goodput),
+            inbound_wire: self.smooth(|r| r.inbound_wire),
+            outbound_wire: self.smooth(|r| r.outbound_wire),
+            transit: self.smooth(|r| r.transit),
+            internal: self.smooth(|r| r.internal),
+            broadcast: self.smooth(|r| r.broadcast),
+            series: std::array::from_fn(|index| self.smooth(|r| r.series[index])),
+        };
+        self.last_smoothed = Some(smoothed);
+        let total = self.total_directions.iter().map(|direction| smoothed.bytes(*direction)).sum();
+
+        let mut bandwidth = DirectionalBandwidth {
+            timestamp: now,
+            inbound: smoothed.inbound,
+            outbound: smoothed.outbound,
+            raw_inbound: raw.inbound,
+            raw_outbound: raw.outbound,
+            inbound_pps: smoothed.inbound_pps,
+            outbound_pps: smoothed.outbound_pps,
+            inbound_goodput: smoothed.inbound_goodput,
+            outbound_goodput: smoothed.outbound_goodput,
+            inbound_wire: smoothed.inbound_wire,
+            outbound_wire: smoothed.outbound_wire,
+            transit: smoothed.transit,
+            internal: smoothed.internal,
+            broadcast: smoothed.broadcast,
+            total,
+            series: smoothed.series[..self.series_count].to_vec(),
+            averages: self.window_averages.averages(),
+            inbound_forecast,
+            outbound_forecast,
+            inbound_burst: bucket.inbound_burst.peaks(),
+            outbound_burst: bucket.outbound_burst.peaks(),
+            inbound_summary: self.inbound_rates.summary(),
+            outbound_summary: self.outbound_rates.summary(),
+            session_totals: self.session_totals,
+            totals_since_reset: self.totals_since_reset,
+            quota: self.quota.as_ref().map(|quota| quota.status(now)),
+            anomalies,
+            alerts: None,
+            kernel: self.kernel_counters.as_mut().and_then(|sampler| sampler.sample(now, self.captured_packets)),
+            capture_drops: self.capture_drops.as_ref().map(|drops| drops.counts()),
+            tcp_windows,
+            active_flows,
+            fragments,
+            size_histogram,
+            session_size_histogram: self.session_size_histogram.clone(),
+        };
 
-        DirectionalBandwidth {
-            inbound: smoothed_inbound,
-            outbound: smoothed_outbound,
+        if let Some(alerts) = &mut self.alerts {
+            bandwidth.alerts = Some(alerts.evaluate(now, bucket.totals.span, |metric| match metric {
+                AlertMetric::Inbound => bandwidth.inbound,
+                AlertMetric::Outbound => bandwidth.outbound,
+                AlertMetric::Total => bandwidth.total,
+                AlertMetric::Transit => bandwidth.transit,
+                AlertMetric::Internal => bandwidth.internal,
+                AlertMetric::Broadcast => bandwidth.broadcast,
+                AlertMetric::InboundPps => bandwidth.inbound_pps,
+                AlertMetric::OutboundPps => bandwidth.outbound_pps,
+            }));
+        }
+
+        bandwidth
+    }
+
+    fn smooth(&self, field: impl Fn(&Rates) -> f64) -> f64 {
+        let latest = self.smoothing_buffer.back().map(&field).unwrap_or_default();
+
+        match self.smoothing {
+            Smoothing::None => latest,
+            Smoothing::MovingAverage(_) => {
+                self.smoothing_buffer.iter().map(field).sum::<f64>() / self.smoothing_buffer.len() as f64
+            }
+            Smoothing::Median(_) => {
+                let mut samples: Vec<f64> = self.smoothing_buffer.iter().map(field).collect();
+                samples.sort_by(|a, b| a.total_cmp(b));
+                let middle = samples.len() / 2;
+                if samples.len().is_multiple_of(2) {
+                    (samples[middle - 1] + samples[middle]) / 2.0
+                } else {
+                    samples[middle]
+                }
+            }
+            Smoothing::Ewma(alpha) => match &self.last_smoothed {
+                Some(previous) => alpha * latest + (1.0 - alpha) * field(previous),
+                None => latest,
+            },
         }
     }
 
@@ -123,49 +1030,56 @@ impl BandwidthCalculator {
             
         (inbound, outbound)
     }
+}
 
-    fn cleanup_old_packets(&mut self) {
-        let cutoff_time = SystemTime::now() - self.window_duration * 2;
-        
-        while let Some(packet) = self.packet_buffer.front() {
-            if packet.timestamp < cutoff_time {
-                self.packet_buffer.pop_front();
-            } else {
-                break;
-            }
-        }
+/// The running monitor task, see `start_bandwidth_monitor`.
+pub struct BandwidthMonitor {
+    stop_tx: oneshot::Sender<()>,
+    task: JoinHandle<Result<()>>,
+}
+
+impl BandwidthMonitor {
+    /// Stops the task after counting what is still queued, and saves the quota state.
+    pub async fn stop(self) -> Result<()> {
+        // Already gone if nobody was listening for the rates any more
+        let _ = self.stop_tx.send(());
+        self.task.await?
     }
 }
 
+/// Runs `calculator` on its own task, closing an interval every update interval.
 pub async fn start_bandwidth_monitor(
     packet_rx: mpsc::Receiver<PacketInfo>,
-    update_interval: Duration,
-    smoothing_samples: usize,
-) -> mpsc::Receiver<DirectionalBandwidth> {
+    command_rx: mpsc::Receiver<MonitorCommand>,
+    mut calculator: BandwidthCalculator,
+) -> (mpsc::Receiver<DirectionalBandwidth>, BandwidthMonitor) {
     let (tx, rx) = mpsc::channel();
-    let mut calculator = BandwidthCalculator::new(
-        Duration::from_secs(1),
-        300, // Keep 5 minutes of history
-        smoothing_samples,
-    );
-
-    tokio::spawn(async move {
-        let mut interval = tokio::time::interval(update_interval);
-        
-        loop {
-            interval.tick().await;
+    let (stop_tx, mut stop_rx) = oneshot::channel();
 
-            while let Ok(packet) = packet_rx.try_recv() {
-                calculator.add_packet(packet);
+    let task = tokio::spawn(async move {
+        let mut interval = tokio::time::interval(calculator.update_interval);
+        // A late tick just makes that bucket longer, rather than a burst of short ones to catch up
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        // The first tick completes immediately
+        interval.tick().await;
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = &mut stop_rx => break,
             }
 
+            calculator.drain(&command_rx, &packet_rx);
             let bandwidth = calculator.calculate_bandwidth();
-            
+
             if tx.send(bandwidth).is_err() {
                 break;
             }
         }
+
+        calculator.drain(&command_rx, &packet_rx);
+        calculator.finish()
     });
 
-    rx
+    (rx, BandwidthMonitor { stop_tx, task })
 }
\ No newline at end of file
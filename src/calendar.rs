@@ -0,0 +1,58 @@
+// UTC calendar arithmetic, enough for quota periods and log timestamps without a date library
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const SECONDS_PER_DAY: u64 = 86400;
+
+/// Formats a time as `YYYY-MM-DD HH:MM UTC`.
+pub fn format_utc(time: SystemTime) -> String {
+    let (date, hours, minutes, _) = split_utc(time);
+    format!("{} {:02}:{:02} UTC", date, hours, minutes)
+}
+
+/// Formats a time as `YYYY-MM-DD HH:MM:SS UTC`.
+pub fn format_utc_seconds(time: SystemTime) -> String {
+    let (date, hours, minutes, seconds) = split_utc(time);
+    format!("{} {:02}:{:02}:{:02} UTC", date, hours, minutes, seconds)
+}
+
+/// Formats a time as RFC 3339 in UTC, `YYYY-MM-DDTHH:MM:SSZ`.
+pub fn format_rfc3339(time: SystemTime) -> String {
+    let (date, hours, minutes, seconds) = split_utc(time);
+    format!("{}T{:02}:{:02}:{:02}Z", date, hours, minutes, seconds)
+}
+
+fn split_utc(time: SystemTime) -> (String, u64, u64, u64) {
+    let secs = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let (year, month, day) = civil_from_days(secs / SECONDS_PER_DAY);
+    let seconds_of_day = secs % SECONDS_PER_DAY;
+    (
+        format!("{:04}-{:02}-{:02}", year, month, day),
+        seconds_of_day / 3600,
+        seconds_of_day % 3600 / 60,
+        seconds_of_day % 60,
+    )
+}
+
+// Days since 1970-01-01 to a Gregorian date (Howard Hinnant's civil_from_days)
+pub fn civil_from_days(days: u64) -> (u64, u32, u32) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z % 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+pub fn days_from_civil(year: u64, month: u32, day: u32) -> u64 {
+    let year = year - u64::from(month <= 2);
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let mp = if month > 2 { month - 3 } else { month + 9 } as u64;
+    let day_of_year = (153 * mp + 2) / 5 + day as u64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
@@ -2,39 +2,234 @@ use anyhow::{Context, Result};
 use pcap::{Capture, Device};
 use pnet::datalink;
 use pnet::packet::ethernet::{EthernetPacket, EtherTypes};
-use pnet::packet::ipv4::Ipv4Packet;
+use pnet::packet::ipv4::{Ipv4Flags, Ipv4Packet};
 use pnet::packet::ipv6::Ipv6Packet;
-use pnet::packet::tcp::TcpPacket;
-use pnet::packet::ip::IpNextHeaderProtocols;
+use pnet::packet::tcp::{TcpOptionNumbers, TcpPacket};
+use pnet::packet::ip::{IpNextHeaderProtocol, IpNextHeaderProtocols};
 use pnet::packet::Packet;
 use pnet::util::MacAddr;
+use crate::fragment::{FragmentKey, FragmentStats, FragmentTracker, IpFragment};
+use crate::tcp_window::{TcpSegment, TcpWindowInfo, TcpWindowTracker};
 use std::collections::HashSet;
-use std::sync::mpsc;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, mpsc};
+use std::time::{Duration, Instant};
 use tokio::task;
 
+const ETHERNET_HEADER_LEN: usize = 14;
+const IPV6_HEADER_LEN: usize = 40;
+const MAX_IPV6_EXTENSION_HEADERS: usize = 8;
+const UDP_HEADER_LEN: u32 = 8;
+const ICMP_HEADER_LEN: u32 = 8;
+const SCTP_COMMON_HEADER_LEN: usize = 12;
+const SCTP_CHUNK_DATA: u8 = 0;
+const SCTP_CHUNK_IDATA: u8 = 64;
+const SCTP_DATA_CHUNK_HEADER_LEN: usize = 16;
+const SCTP_IDATA_CHUNK_HEADER_LEN: usize = 20;
+
+// Where the transport header starts within a captured frame and how many bytes
+// the IP layer says follow it (excluding IPv6 extension headers)
+struct TransportLocation {
+    src: IpAddr,
+    dst: IpAddr,
+    protocol: IpNextHeaderProtocol,
+    offset: usize,
+    transport_length: u32,
+    fragment: Option<IpFragment>,
+}
+
+impl TransportLocation {
+    // Only unfragmented packets and first fragments start with a transport header
+    fn has_transport_header(&self) -> bool {
+        self.fragment.is_none_or(|fragment| fragment.is_first())
+    }
+}
+
+// Ethernet preamble + start frame delimiter, frame check sequence and minimum inter-frame gap
+const ETHERNET_PREAMBLE_LEN: u32 = 8;
+const ETHERNET_FCS_LEN: u32 = 4;
+const ETHERNET_IFG_LEN: u32 = 12;
+const ETHERNET_MIN_FRAME_LEN: u32 = 60;
+// How often the capture thread reads pcap's drop counters
+const CAPTURE_STATS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// What the wire adds to every frame the kernel counts: FCS, preamble and inter-frame gap
+pub const ETHERNET_WIRE_OVERHEAD: u32 = ETHERNET_FCS_LEN + ETHERNET_PREAMBLE_LEN + ETHERNET_IFG_LEN;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum AccountingLayer {
+    /// Whole captured frame including the link-layer header (L2)
+    Frame,
+    /// IP packet, excluding link-layer headers (L3)
+    Ip,
+    /// Transport payload only, excluding all headers (L4)
+    Payload,
+    /// Bytes occupied on the Ethernet wire: frame, FCS, padding, preamble and inter-frame gap
+    Wire,
+}
+
+impl AccountingLayer {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AccountingLayer::Frame => "frame",
+            AccountingLayer::Ip => "ip",
+            AccountingLayer::Payload => "payload",
+            AccountingLayer::Wire => "wire",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FlowKey {
+    pub protocol: IpNextHeaderProtocol,
+    pub src: IpAddr,
+    pub src_port: u16,
+    pub dst: IpAddr,
+    pub dst_port: u16,
+}
+
+impl FlowKey {
+    pub fn reversed(&self) -> Self {
+        Self {
+            protocol: self.protocol,
+            src: self.dst,
+            src_port: self.dst_port,
+            dst: self.src,
+            dst_port: self.src_port,
+        }
+    }
+}
+
 pub struct PacketCapture {
     interface: String,
     filter: String,
-    payload_only: bool,
+    accounting_layer: AccountingLayer,
+    series: Vec<SeriesFilter>,
+    fragment_tracker: Arc<Mutex<FragmentTracker>>,
+    drops: Arc<CaptureDrops>,
 }
 
-#[derive(Debug, Clone)]
+/// Packets pcap lost since the capture started, as of its latest statistics.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CaptureDropCounts {
+    /// No room in the capture buffer: the capture fell behind
+    pub dropped: u64,
+    /// Dropped by the interface or its driver, where the platform reports it
+    pub interface_dropped: u64,
+}
+
+/// Drop counters written by the capture thread and read by the calculator.
+#[derive(Debug, Default)]
+pub struct CaptureDrops {
+    dropped: AtomicU64,
+    interface_dropped: AtomicU64,
+}
+
+impl CaptureDrops {
+    pub fn update(&self, counts: CaptureDropCounts) {
+        self.dropped.store(counts.dropped, Ordering::Relaxed);
+        self.interface_dropped.store(counts.interface_dropped, Ordering::Relaxed);
+    }
+
+    pub fn counts(&self) -> CaptureDropCounts {
+        CaptureDropCounts {
+            dropped: self.dropped.load(Ordering::Relaxed),
+            interface_dropped: self.interface_dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Most `--series` a capture can tag packets with, one bit each in `PacketInfo::series`
+pub const MAX_SERIES: usize = 8;
+
+/// A named BPF filter; packets matching it are graphed as their own series.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SeriesFilter {
+    pub name: String,
+    pub filter: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, clap::ValueEnum)]
 pub enum TrafficDirection {
+    /// Unicast to one of our interfaces
     Inbound,
+    /// Unicast from one of our interfaces
     Outbound,
-    Unknown,
+    /// Neither MAC is ours: forwarded or bridged traffic seen in promiscuous mode
+    Transit,
+    /// Both MACs are ours: traffic between local interfaces
+    Internal,
+    /// Sent to a broadcast or multicast MAC address
+    Broadcast,
+}
+
+impl TrafficDirection {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TrafficDirection::Inbound => "inbound",
+            TrafficDirection::Outbound => "outbound",
+            TrafficDirection::Transit => "transit",
+            TrafficDirection::Internal => "internal",
+            TrafficDirection::Broadcast => "broadcast",
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct PacketInfo {
     pub timestamp: std::time::SystemTime,
     pub size: u32,
+    /// Length of the frame on the interface, whatever the accounting layer
+    pub frame_size: u32,
+    pub payload_size: u32,
     pub direction: TrafficDirection,
+    pub flow: Option<FlowKey>,
+    pub fragments: FragmentStats,
+    pub tcp_window: Option<TcpWindowInfo>,
+    /// Bit `i` is set when the packet matched series `i`
+    pub series: u8,
 }
 
 impl PacketCapture {
-    pub fn new(interface: String, filter: String, payload_only: bool) -> Self {
-        Self { interface, filter, payload_only }
+    pub fn new(interface: String, filter: String, accounting_layer: AccountingLayer) -> Self {
+        Self {
+            interface,
+            filter,
+            accounting_layer,
+            series: Vec::new(),
+            fragment_tracker: Arc::new(Mutex::new(FragmentTracker::new())),
+            drops: Arc::new(CaptureDrops::default()),
+        }
+    }
+
+    /// pcap's drop counters, kept up to date while the capture runs.
+    pub fn capture_drops(&self) -> Arc<CaptureDrops> {
+        Arc::clone(&self.drops)
+    }
+
+    /// The capture's fragment tracker, for expiring incomplete datagrams while no packets arrive.
+    pub fn fragment_tracker(&self) -> Arc<Mutex<FragmentTracker>> {
+        Arc::clone(&self.fragment_tracker)
+    }
+
+    /// Matches every captured packet against these filters, at most `MAX_SERIES` of them.
+    pub fn with_series(mut self, series: Vec<SeriesFilter>) -> Self {
+        self.series = series;
+        self
+    }
+
+    /// Speed of `interface` in bytes/s as reported by the kernel, if it knows it
+    /// (Wi-Fi, virtual interfaces and "any" usually have none).
+    pub fn link_speed(interface: &str) -> Option<f64> {
+        let contents = std::fs::read_to_string(format!("/sys/class/net/{}/speed", interface)).ok()?;
+        Self::parse_link_speed(&contents)
+    }
+
+    /// Parses `/sys/class/net/<iface>/speed`, which is in Mb/s and -1 when unknown.
+    pub fn parse_link_speed(contents: &str) -> Option<f64> {
+        let mbps: i64 = contents.trim().parse().ok()?;
+        (mbps > 0).then(|| mbps as f64 * 1_000_000.0 / 8.0)
     }
 
     fn get_local_macs(interface_name: &str) -> HashSet<MacAddr> {
@@ -56,75 +251,297 @@ impl PacketCapture {
         local_macs
     }
 
-    fn get_payload_size(packet_data: &[u8]) -> u32 {
+    fn locate_transport(packet_data: &[u8]) -> Option<TransportLocation> {
+        let eth_packet = EthernetPacket::new(packet_data)?;
+
+        match eth_packet.get_ethertype() {
+            EtherTypes::Ipv4 => {
+                let ipv4_packet = Ipv4Packet::new(eth_packet.payload())?;
+                let header_length = (ipv4_packet.get_header_length() as u32) * 4;
+                let total_length = ipv4_packet.get_total_length() as u32;
+                let src = IpAddr::V4(ipv4_packet.get_source());
+                let dst = IpAddr::V4(ipv4_packet.get_destination());
+                let protocol = ipv4_packet.get_next_level_protocol();
+                let transport_length = total_length.saturating_sub(header_length);
+
+                let more_fragments = ipv4_packet.get_flags() & Ipv4Flags::MoreFragments != 0;
+                let fragment_offset = ipv4_packet.get_fragment_offset() as u32 * 8;
+                let fragment = (more_fragments || fragment_offset > 0).then_some(IpFragment {
+                    key: FragmentKey {
+                        src,
+                        dst,
+                        protocol,
+                        identification: ipv4_packet.get_identification() as u32,
+                    },
+                    offset: fragment_offset,
+                    more_fragments,
+                    length: transport_length,
+                });
+
+                Some(TransportLocation {
+                    src,
+                    dst,
+                    protocol,
+                    offset: ETHERNET_HEADER_LEN + header_length as usize,
+                    transport_length,
+                    fragment,
+                })
+            }
+            EtherTypes::Ipv6 => {
+                let ipv6_packet = Ipv6Packet::new(eth_packet.payload())?;
+                let src = IpAddr::V6(ipv6_packet.get_source());
+                let dst = IpAddr::V6(ipv6_packet.get_destination());
+                let mut protocol = ipv6_packet.get_next_header();
+                let mut offset = ETHERNET_HEADER_LEN + IPV6_HEADER_LEN;
+                let mut extension_length = 0u32;
+                let mut fragment_header = None;
+
+                // Walk the extension header chain until we reach the upper-layer header
+                for _ in 0..MAX_IPV6_EXTENSION_HEADERS {
+                    let header_length = match protocol {
+                        IpNextHeaderProtocols::Hopopt
+                        | IpNextHeaderProtocols::Ipv6Route
+                        | IpNextHeaderProtocols::Ipv6Opts => {
+                            (*packet_data.get(offset + 1)? as usize + 1) * 8
+                        }
+                        IpNextHeaderProtocols::Ipv6Frag => {
+                            let header = packet_data.get(offset..offset + 8)?;
+                            let offset_and_flags = u16::from_be_bytes([header[2], header[3]]);
+                            fragment_header = Some((
+                                (offset_and_flags >> 3) as u32 * 8,
+                                offset_and_flags & 0x1 != 0,
+                                u32::from_be_bytes([header[4], header[5], header[6], header[7]]),
+                            ));
+                            8
+                        }
+                        IpNextHeaderProtocols::Ah => (*packet_data.get(offset + 1)? as usize + 2) * 4,
+                        _ => break,
+                    };
+
+                    protocol = IpNextHeaderProtocol::new(*packet_data.get(offset)?);
+                    offset += header_length;
+                    extension_length += header_length as u32;
+                }
+
+                let transport_length = (ipv6_packet.get_payload_length() as u32)
+                    .saturating_sub(extension_length);
+                let fragment = fragment_header.map(|(fragment_offset, more_fragments, identification)| IpFragment {
+                    key: FragmentKey {
+                        src,
+                        dst,
+                        protocol,
+                        identification,
+                    },
+                    offset: fragment_offset,
+                    more_fragments,
+                    length: transport_length,
+                });
+
+                Some(TransportLocation {
+                    src,
+                    dst,
+                    protocol,
+                    offset,
+                    transport_length,
+                    fragment,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn get_accounted_size(layer: AccountingLayer, packet_data: &[u8], frame_len: u32) -> u32 {
+        match layer {
+            AccountingLayer::Frame => frame_len,
+            AccountingLayer::Ip => Self::get_ip_size(packet_data, frame_len),
+            AccountingLayer::Payload => Self::get_payload_size(packet_data),
+            AccountingLayer::Wire => Self::wire_size(frame_len),
+        }
+    }
+
+    /// Time on the wire of a frame of `frame_len` captured bytes, in bytes.
+    pub fn wire_size(frame_len: u32) -> u32 {
+        frame_len.max(ETHERNET_MIN_FRAME_LEN) + ETHERNET_WIRE_OVERHEAD
+    }
+
+    fn get_ip_size(packet_data: &[u8], frame_len: u32) -> u32 {
         if let Some(eth_packet) = EthernetPacket::new(packet_data) {
             match eth_packet.get_ethertype() {
                 EtherTypes::Ipv4 => {
                     if let Some(ipv4_packet) = Ipv4Packet::new(eth_packet.payload()) {
-                        let total_length = ipv4_packet.get_total_length() as u32;
-                        let header_length = (ipv4_packet.get_header_length() as u32) * 4;
-                        
-                        // For TCP, subtract TCP header as well
-                        if ipv4_packet.get_next_level_protocol() == IpNextHeaderProtocols::Tcp {
-                            if let Some(tcp_packet) = TcpPacket::new(ipv4_packet.payload()) {
-                                let tcp_header_length = (tcp_packet.get_data_offset() as u32) * 4;
-                                return total_length.saturating_sub(header_length + tcp_header_length);
-                            }
-                        }
-                        
-                        // For other protocols, just subtract IP header
-                        return total_length.saturating_sub(header_length);
+                        return ipv4_packet.get_total_length() as u32;
                     }
                 }
                 EtherTypes::Ipv6 => {
                     if let Some(ipv6_packet) = Ipv6Packet::new(eth_packet.payload()) {
-                        let payload_length = ipv6_packet.get_payload_length() as u32;
-                        
-                        // For TCP, subtract TCP header
-                        if ipv6_packet.get_next_header() == IpNextHeaderProtocols::Tcp {
-                            if let Some(tcp_packet) = TcpPacket::new(ipv6_packet.payload()) {
-                                let tcp_header_length = (tcp_packet.get_data_offset() as u32) * 4;
-                                return payload_length.saturating_sub(tcp_header_length);
-                            }
-                        }
-                        
-                        return payload_length;
+                        return IPV6_HEADER_LEN as u32 + ipv6_packet.get_payload_length() as u32;
                     }
                 }
                 _ => {}
             }
         }
-        
+
+        // Not IP: everything after the link-layer header
+        frame_len.saturating_sub(ETHERNET_HEADER_LEN as u32)
+    }
+
+    pub fn get_payload_size(packet_data: &[u8]) -> u32 {
+        if let Some(location) = Self::locate_transport(packet_data) {
+            // Non-first fragments are pure payload of the original datagram
+            if !location.has_transport_header() {
+                return location.transport_length;
+            }
+
+            let transport = packet_data.get(location.offset..).unwrap_or_default();
+
+            return match location.protocol {
+                IpNextHeaderProtocols::Tcp => match TcpPacket::new(transport) {
+                    Some(tcp_packet) => {
+                        let tcp_header_length = (tcp_packet.get_data_offset() as u32) * 4;
+                        location.transport_length.saturating_sub(tcp_header_length)
+                    }
+                    None => location.transport_length,
+                },
+                IpNextHeaderProtocols::Udp => location.transport_length.saturating_sub(UDP_HEADER_LEN),
+                IpNextHeaderProtocols::Icmp | IpNextHeaderProtocols::Icmpv6 => {
+                    location.transport_length.saturating_sub(ICMP_HEADER_LEN)
+                }
+                IpNextHeaderProtocols::Sctp => Self::get_sctp_payload_size(transport, location.transport_length),
+                IpNextHeaderProtocols::Dccp => match transport.get(4) {
+                    Some(data_offset) => location.transport_length.saturating_sub(*data_offset as u32 * 4),
+                    None => location.transport_length,
+                },
+                // For other protocols, just subtract the IP headers
+                _ => location.transport_length,
+            };
+        }
+
         // Fallback to full packet size if we can't parse headers
         packet_data.len() as u32
     }
 
-    fn determine_direction(packet_data: &[u8], local_macs: &HashSet<MacAddr>) -> TrafficDirection {
-        if let Some(eth_packet) = EthernetPacket::new(packet_data) {
-            let src_mac = eth_packet.get_source();
-            let dst_mac = eth_packet.get_destination();
-            
-            let src_is_local = local_macs.contains(&src_mac);
-            let dst_is_local = local_macs.contains(&dst_mac);
-            
-            // Check for broadcast/multicast destinations
-            let is_broadcast = dst_mac == MacAddr::broadcast();
-            let is_multicast = dst_mac.is_multicast();
-            
-            match (src_is_local, dst_is_local, is_broadcast, is_multicast) {
-                // Source is our interface -> outbound traffic
-                (true, false, _, _) => TrafficDirection::Outbound,
-                // Destination is our interface -> inbound traffic  
-                (false, true, _, _) => TrafficDirection::Inbound,
-                // Broadcast/multicast from our interface -> outbound
-                (true, _, true, _) | (true, _, _, true) => TrafficDirection::Outbound,
-                // Broadcast/multicast to us -> inbound
-                (false, _, true, _) | (false, _, _, true) => TrafficDirection::Inbound,
-                // Internal traffic (both local) or external (neither local) -> unknown
-                _ => TrafficDirection::Unknown,
+    fn get_sctp_payload_size(sctp_data: &[u8], transport_length: u32) -> u32 {
+        // Only user data carried in DATA/I-DATA chunks counts as payload; control chunks are overhead.
+        // The walk ends at the length IP declares, so Ethernet padding is never read as chunks
+        let end = transport_length as usize;
+        let mut payload = 0usize;
+        let mut offset = SCTP_COMMON_HEADER_LEN;
+
+        while offset + 4 <= end {
+            let Some(chunk_header) = sctp_data.get(offset..offset + 4) else {
+                // Cut off by the snaplen: assume the rest is one DATA chunk, the usual case for bulk transfers
+                payload += (end - offset).saturating_sub(SCTP_DATA_CHUNK_HEADER_LEN);
+                break;
+            };
+            let chunk_type = chunk_header[0];
+            let chunk_length = u16::from_be_bytes([chunk_header[2], chunk_header[3]]) as usize;
+            if chunk_length < 4 {
+                break;
             }
-        } else {
-            TrafficDirection::Unknown
+            let chunk_length = chunk_length.min(end - offset);
+
+            payload += match chunk_type {
+                SCTP_CHUNK_DATA => chunk_length.saturating_sub(SCTP_DATA_CHUNK_HEADER_LEN),
+                SCTP_CHUNK_IDATA => chunk_length.saturating_sub(SCTP_IDATA_CHUNK_HEADER_LEN),
+                _ => 0,
+            };
+
+            // Chunks are padded to a multiple of four bytes
+            offset += chunk_length.div_ceil(4) * 4;
+        }
+
+        payload as u32
+    }
+
+    fn parse_flow(packet_data: &[u8], location: &TransportLocation) -> Option<FlowKey> {
+        match location.protocol {
+            IpNextHeaderProtocols::Tcp
+            | IpNextHeaderProtocols::Udp
+            | IpNextHeaderProtocols::Sctp
+            | IpNextHeaderProtocols::Dccp => {}
+            _ => return None,
+        }
+        if !location.has_transport_header() {
+            return None;
+        }
+
+        // All four protocols start with the source and destination ports
+        let ports = packet_data.get(location.offset..location.offset + 4)?;
+        Some(FlowKey {
+            protocol: location.protocol,
+            src: location.src,
+            src_port: u16::from_be_bytes([ports[0], ports[1]]),
+            dst: location.dst,
+            dst_port: u16::from_be_bytes([ports[2], ports[3]]),
+        })
+    }
+
+    pub fn identify_flow(
+        packet_data: &[u8],
+        fragment_tracker: &mut FragmentTracker,
+        now: std::time::SystemTime,
+    ) -> (Option<FlowKey>, FragmentStats) {
+        let Some(location) = Self::locate_transport(packet_data) else {
+            return (None, FragmentStats::default());
+        };
+        let flow = Self::parse_flow(packet_data, &location);
+
+        match location.fragment {
+            Some(fragment) => fragment_tracker.observe(&fragment, flow, now),
+            None => (flow, FragmentStats::default()),
+        }
+    }
+
+    fn parse_tcp_segment(packet_data: &[u8]) -> Option<TcpSegment> {
+        let location = Self::locate_transport(packet_data)?;
+        // Fragmented segments carry only part of the data, so their sequence space can't be checked
+        if location.protocol != IpNextHeaderProtocols::Tcp || location.fragment.is_some() {
+            return None;
+        }
+
+        let tcp_packet = TcpPacket::new(packet_data.get(location.offset..)?)?;
+        let tcp_header_length = (tcp_packet.get_data_offset() as u32) * 4;
+        let window_scale = tcp_packet
+            .get_options_iter()
+            .find(|option| option.get_number() == TcpOptionNumbers::WSCALE)
+            .and_then(|option| option.payload().first().copied());
+
+        Some(TcpSegment {
+            flow: FlowKey {
+                protocol: location.protocol,
+                src: location.src,
+                src_port: tcp_packet.get_source(),
+                dst: location.dst,
+                dst_port: tcp_packet.get_destination(),
+            },
+            sequence: tcp_packet.get_sequence(),
+            acknowledgement: tcp_packet.get_acknowledgement(),
+            flags: tcp_packet.get_flags(),
+            window: tcp_packet.get_window(),
+            window_scale,
+            payload_len: location.transport_length.saturating_sub(tcp_header_length),
+        })
+    }
+
+    pub fn determine_direction(packet_data: &[u8], local_macs: &HashSet<MacAddr>) -> TrafficDirection {
+        // Too short to carry MAC addresses, so nothing says it was ours
+        let Some(eth_packet) = EthernetPacket::new(packet_data) else {
+            return TrafficDirection::Transit;
+        };
+
+        let src_mac = eth_packet.get_source();
+        let dst_mac = eth_packet.get_destination();
+
+        // The broadcast address is itself a multicast address
+        if dst_mac.is_multicast() {
+            return TrafficDirection::Broadcast;
+        }
+
+        match (local_macs.contains(&src_mac), local_macs.contains(&dst_mac)) {
+            (true, false) => TrafficDirection::Outbound,
+            (false, true) => TrafficDirection::Inbound,
+            (true, true) => TrafficDirection::Internal,
+            (false, false) => TrafficDirection::Transit,
         }
     }
 
@@ -133,9 +550,12 @@ impl PacketCapture {
         let interface = self.interface.clone();
         let filter = self.filter.clone();
 
-        let payload_only = self.payload_only;
+        let accounting_layer = self.accounting_layer;
+        let series = self.series.clone();
+        let fragment_tracker = self.fragment_tracker();
+        let drops = self.capture_drops();
         task::spawn_blocking(move || {
-            Self::capture_packets(interface, filter, payload_only, tx)
+            Self::capture_packets(interface, filter, accounting_layer, series, fragment_tracker, drops, tx)
         });
 
         Ok(rx)
@@ -144,7 +564,10 @@ impl PacketCapture {
     fn capture_packets(
         interface: String,
         filter: String,
-        payload_only: bool,
+        accounting_layer: AccountingLayer,
+        series: Vec<SeriesFilter>,
+        fragment_tracker: Arc<Mutex<FragmentTracker>>,
+        drops: Arc<CaptureDrops>,
         tx: mpsc::Sender<PacketInfo>,
     ) -> Result<()> {
         let device = if interface == "any" {
@@ -171,28 +594,75 @@ impl PacketCapture {
             .promisc(true)
             .snaplen(65535)
             .timeout(1000)
+            // Deliver packets as they arrive rather than in buffered batches, so
+            // sub-second intervals see them in the interval they belong to
+            .immediate_mode(true)
             .open()?;
 
         cap.filter(&filter, true)
             .context("Failed to set packet filter")?;
 
+        // Compiled against the open capture so they match its link type; one capture serves every series
+        let series_programs = series
+            .iter()
+            .take(MAX_SERIES)
+            .map(|series| {
+                cap.compile(&series.filter, true)
+                    .with_context(|| format!("Invalid filter for series '{}'", series.name))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
         let local_macs = Self::get_local_macs(&interface);
+        let mut window_tracker = TcpWindowTracker::new();
 
+        let mut last_stats = Instant::now();
         loop {
+            // Also reached on read timeouts, so the counters stay current on an idle link
+            if last_stats.elapsed() >= CAPTURE_STATS_INTERVAL {
+                last_stats = Instant::now();
+                if let Ok(stats) = cap.stats() {
+                    drops.update(CaptureDropCounts {
+                        dropped: stats.dropped as u64,
+                        interface_dropped: stats.if_dropped as u64,
+                    });
+                }
+            }
+
             match cap.next_packet() {
                 Ok(packet) => {
                     let direction = Self::determine_direction(&packet.data, &local_macs);
                     
-                    let size = if payload_only {
-                        Self::get_payload_size(&packet.data)
-                    } else {
-                        packet.header.caplen
-                    };
+                    let size = Self::get_accounted_size(accounting_layer, packet.data, packet.header.len);
+                    let payload_size = Self::get_payload_size(packet.data);
                     
+                    let timestamp = std::time::UNIX_EPOCH
+                        + Duration::new(packet.header.ts.tv_sec as u64, packet.header.ts.tv_usec as u32 * 1_000);
+
+                    let tcp_window = Self::parse_tcp_segment(packet.data)
+                        .map(|segment| window_tracker.observe(&segment, timestamp));
+                    let (flow, fragments) = {
+                        let mut fragment_tracker = fragment_tracker.lock().unwrap_or_else(PoisonError::into_inner);
+                        let (flow, mut fragments) = Self::identify_flow(packet.data, &mut fragment_tracker, timestamp);
+                        fragments.reassembly_timeouts += fragment_tracker.expire(timestamp);
+                        (flow, fragments)
+                    };
+
+                    let series = series_programs
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, program)| program.filter(packet.data))
+                        .fold(0u8, |mask, (index, _)| mask | 1 << index);
+
                     let packet_info = PacketInfo {
-                        timestamp: std::time::SystemTime::now(),
+                        timestamp,
                         size,
+                        frame_size: packet.header.len,
+                        payload_size,
                         direction,
+                        flow,
+                        fragments,
+                        tcp_window,
+                        series,
                     };
 
                     if tx.send(packet_info).is_err() {
@@ -1,28 +1,86 @@
 use clap::Parser;
+use crate::alert::{AlertMetric, AlertRule, Comparison};
+use crate::anomaly::BaselineMethod;
+use crate::bandwidth::{Smoothing, SmoothingMode};
+use crate::capture::{AccountingLayer, SeriesFilter, TrafficDirection};
+use crate::counters::TrafficSource;
+use crate::history::RetentionTier;
+use crate::output::{DEFAULT_FORMAT, FormatPiece, LineFormat, OutputField};
+use crate::quota::{QuotaConfig, QuotaDirection, QuotaPeriod};
+use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Parser, Debug)]
 #[command(name = "tcpgraph")]
 #[command(about = "A terminal-based network bandwidth monitor")]
 pub struct Args {
-    #[arg(short, long, help = "Network interface to monitor")]
+    #[arg(short, long, help = "Network interface to monitor (\"any\" for all but loopback with --source counters)")]
     pub interface: String,
 
-    #[arg(short, long, help = "PCAP filter expression")]
+    #[arg(short, long, default_value = "", help = "PCAP filter expression (required with --source pcap)")]
     pub filter: String,
 
+    #[arg(
+        long,
+        value_enum,
+        default_value = "pcap",
+        help = "Where rates come from: pcap (packet capture) or counters (/proc/net/dev, no root needed)"
+    )]
+    pub source: TrafficSource,
+
     #[arg(
         long,
         default_value = "1",
-        help = "Graph update interval in seconds"
+        value_parser = parse_interval,
+        help = "Graph update interval, in seconds or with a unit (e.g. 250ms, 1.5s)"
+    )]
+    pub interval: Duration,
+
+    #[arg(
+        long,
+        value_parser = parse_interval,
+        help = "Averaging window, in seconds or with a unit (default: same as --interval)"
+    )]
+    pub window: Option<Duration>,
+
+    #[arg(
+        long,
+        default_value = "300",
+        help = "Number of intervals of rates kept as the anomaly baseline and for fitting the forecast (the chart keeps its own history, see --points and --retention)"
+    )]
+    pub history: usize,
+
+    #[arg(
+        long,
+        default_value = "100",
+        help = "Number of data points visible in the chart"
+    )]
+    pub points: usize,
+
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_parser = parse_retention,
+        default_value = "1s:10m,10s:6h,1m:7d",
+        help = "Long-term history tiers as RESOLUTION:SPAN, each with min/avg/max per point"
     )]
-    pub interval: u64,
+    pub retention: Vec<RetentionTier>,
 
     #[arg(long, help = "Total monitoring duration in seconds")]
     pub duration: Option<u64>,
 
     #[arg(
         long,
-        help = "Count only payload data (excludes headers) for more accurate application-layer bandwidth"
+        value_enum,
+        default_value = "frame",
+        help = "Layer at which bytes are counted: frame (L2), ip (L3), payload (L4) or wire (incl. preamble/IFG)"
+    )]
+    pub layer: AccountingLayer,
+
+    #[arg(
+        long,
+        conflicts_with = "layer",
+        help = "Count only payload data (excludes headers); shorthand for --layer payload"
     )]
     pub payload_only: bool,
 
@@ -32,10 +90,421 @@ pub struct Args {
         help = "Number of samples to use for smoothing bandwidth calculations (reduces spikes)"
     )]
     pub smoothing: usize,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "sma",
+        help = "Smoothing algorithm: sma (moving average), ewma, median or none"
+    )]
+    pub smoothing_mode: SmoothingMode,
+
+    #[arg(
+        long,
+        default_value = "0.3",
+        conflicts_with = "half_life",
+        help = "Weight of the newest sample for --smoothing-mode ewma (0-1]"
+    )]
+    pub ewma_alpha: f64,
+
+    #[arg(
+        long,
+        help = "Half-life in intervals for --smoothing-mode ewma (alternative to --ewma-alpha)"
+    )]
+    pub half_life: Option<f64>,
+
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_value = "inbound,outbound",
+        help = "Directions added up in the Total rate: inbound, outbound, transit, internal, broadcast"
+    )]
+    pub total: Vec<TrafficDirection>,
+
+    #[arg(
+        long,
+        value_parser = parse_series,
+        help = "Extra series graphed from the same capture, as NAME=BPF (repeatable, up to 8)"
+    )]
+    pub series: Vec<SeriesFilter>,
+
+    #[arg(
+        long,
+        value_parser = parse_bytes,
+        help = "Data budget per --quota-period, e.g. 500GB or 20GiB"
+    )]
+    pub quota: Option<u64>,
+
+    #[arg(long, value_enum, default_value = "month", help = "Period after which the quota resets")]
+    pub quota_period: QuotaPeriod,
+
+    #[arg(
+        long,
+        default_value = "1",
+        value_parser = clap::value_parser!(u32).range(1..=28),
+        help = "Day of the month (1-28) on which a monthly quota resets"
+    )]
+    pub quota_start_day: u32,
+
+    #[arg(long, value_enum, default_value = "both", help = "Traffic counted against the quota")]
+    pub quota_direction: QuotaDirection,
+
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "80,95",
+        help = "Quota usage percentages at which to warn"
+    )]
+    pub quota_warn: Vec<f64>,
+
+    #[arg(
+        long,
+        help = "File that keeps quota usage across restarts (default: ~/.local/state/tcpgraph/quota-<interface>)"
+    )]
+    pub quota_state: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value = "off",
+        help = "Flag anomalous intervals against a baseline of the --history intervals: mad (median), mean or off"
+    )]
+    pub anomaly: BaselineMethod,
+
+    #[arg(
+        long,
+        default_value = "4",
+        help = "Standard deviations from the baseline at which an interval is flagged"
+    )]
+    pub anomaly_sigma: f64,
+
+    #[arg(long, help = "File to append detected anomalies to")]
+    pub anomaly_log: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_parser = parse_alert,
+        help = "Alert rule such as 'in > 800Mbps for 10s' or 'out_pps < 100 for 1m' (repeatable)"
+    )]
+    pub alert: Vec<AlertRule>,
+
+    #[arg(
+        long,
+        help = "Command run with sh -c when an alert fires or clears, details in TCPGRAPH_ALERT_* variables"
+    )]
+    pub alert_command: Option<String>,
+
+    #[arg(
+        long,
+        default_value = "10",
+        help = "Percentage the value must move back past the threshold before a firing alert clears"
+    )]
+    pub alert_hysteresis: f64,
+
+    #[arg(
+        long,
+        value_parser = parse_interval,
+        help = "Project the bandwidth trend this far ahead on the chart, e.g. 5m"
+    )]
+    pub forecast: Option<Duration>,
+
+    #[arg(
+        long,
+        value_parser = parse_rate,
+        help = "Link capacity, e.g. 1G or 100Mbps (default: the speed in /sys/class/net/<interface>/speed)"
+    )]
+    pub link_speed: Option<f64>,
+
+    #[arg(long, help = "Print one line per interval to stdout instead of the full-screen UI")]
+    pub no_tui: bool,
+
+    #[arg(
+        long,
+        requires = "no_tui",
+        default_value = DEFAULT_FORMAT,
+        value_parser = parse_format,
+        help = "Line printed per interval with --no-tui; {field} or {field:width} placeholders"
+    )]
+    pub format: LineFormat,
 }
 
 impl Args {
     pub fn parse_args() -> Self {
         Args::parse()
     }
-}
\ No newline at end of file
+
+    pub fn window(&self) -> Duration {
+        self.window.unwrap_or(self.interval)
+    }
+
+    pub fn smoothing(&self) -> Smoothing {
+        match self.smoothing_mode {
+            SmoothingMode::Sma => Smoothing::MovingAverage(self.smoothing),
+            SmoothingMode::Median => Smoothing::Median(self.smoothing),
+            SmoothingMode::None => Smoothing::None,
+            SmoothingMode::Ewma => match self.half_life {
+                Some(half_life) => Smoothing::ewma_from_half_life(half_life),
+                None => Smoothing::Ewma(self.ewma_alpha),
+            },
+        }
+    }
+
+    pub fn quota_config(&self) -> Option<QuotaConfig> {
+        let limit_bytes = self.quota?;
+        let mut warn_percents = self.quota_warn.clone();
+        warn_percents.sort_by(|a, b| a.total_cmp(b));
+
+        Some(QuotaConfig {
+            limit_bytes,
+            period: self.quota_period,
+            direction: self.quota_direction,
+            start_day: self.quota_start_day,
+            warn_percents,
+            state_file: self.quota_state.clone().unwrap_or_else(|| default_quota_state(&self.interface)),
+        })
+    }
+
+    pub fn accounting_layer(&self) -> AccountingLayer {
+        if self.payload_only {
+            AccountingLayer::Payload
+        } else {
+            self.layer
+        }
+    }
+}
+
+/// Parses an interval such as `2`, `1.5s`, `250ms`, `10m`, `6h` or `7d`; a bare number is seconds.
+pub fn parse_interval(value: &str) -> Result<Duration, String> {
+    let value = value.trim();
+    let (number, scale) = if let Some(ms) = value.strip_suffix("ms") {
+        (ms, 0.001)
+    } else if let Some(secs) = value.strip_suffix('s') {
+        (secs, 1.0)
+    } else if let Some(minutes) = value.strip_suffix('m') {
+        (minutes, 60.0)
+    } else if let Some(hours) = value.strip_suffix('h') {
+        (hours, 3600.0)
+    } else if let Some(days) = value.strip_suffix('d') {
+        (days, 86400.0)
+    } else {
+        (value, 1.0)
+    };
+
+    let number: f64 = number
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid interval '{}', expected e.g. 2, 1.5s or 250ms", value))?;
+    Duration::try_from_secs_f64(number * scale)
+        .map_err(|_| format!("invalid interval '{}'", value))
+}
+
+/// Parses a retention tier written as `RESOLUTION:SPAN`, e.g. `10s:6h`.
+pub fn parse_retention(value: &str) -> Result<RetentionTier, String> {
+    let (resolution, span) = value
+        .split_once(':')
+        .ok_or_else(|| format!("invalid retention '{}', expected RESOLUTION:SPAN such as 10s:6h", value))?;
+    let tier = RetentionTier {
+        resolution: parse_interval(resolution)?,
+        span: parse_interval(span)?,
+    };
+
+    if tier.resolution < Duration::from_millis(1) || tier.span < tier.resolution {
+        return Err(format!("invalid retention '{}', the span must cover at least one point of 1ms or more", value));
+    }
+    Ok(tier)
+}
+
+/// Parses a byte count such as `500GB`, `1.5TB` or `20GiB`; a bare number is bytes.
+pub fn parse_bytes(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+
+    let scale = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1.0,
+        "kb" => 1e3,
+        "mb" => 1e6,
+        "gb" => 1e9,
+        "tb" => 1e12,
+        "kib" => 1024.0,
+        "mib" => 1024.0 * 1024.0,
+        "gib" => 1024.0 * 1024.0 * 1024.0,
+        "tib" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        _ => return Err(format!("unknown unit in '{}', expected B, kB, MB, GB, TB or KiB..TiB", value)),
+    };
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid size '{}', expected e.g. 500GB", value))?;
+
+    let bytes = (number * scale).round();
+    if bytes < 1.0 {
+        return Err(format!("size '{}' must be at least one byte", value));
+    }
+    Ok(bytes as u64)
+}
+
+/// Parses a series written as `NAME=BPF`, e.g. `https=tcp port 443`.
+pub fn parse_series(value: &str) -> Result<SeriesFilter, String> {
+    let (name, filter) = value
+        .split_once('=')
+        .ok_or_else(|| format!("invalid series '{}', expected NAME=BPF such as 'https=tcp port 443'", value))?;
+    let (name, filter) = (name.trim(), filter.trim());
+    if name.is_empty() || filter.is_empty() {
+        return Err(format!("invalid series '{}', both the name and the filter are required", value));
+    }
+
+    Ok(SeriesFilter {
+        name: name.to_string(),
+        filter: filter.to_string(),
+    })
+}
+
+/// Parses an alert rule written as `METRIC > VALUE [for DURATION]` (or `<`), e.g. `in > 800Mbps for 10s`.
+pub fn parse_alert(value: &str) -> Result<AlertRule, String> {
+    let (condition, duration) = match value.split_once(" for ") {
+        Some((condition, duration)) => (condition, parse_interval(duration)?),
+        None => (value, Duration::ZERO),
+    };
+
+    let (metric, comparison, threshold) = if let Some((metric, threshold)) = condition.split_once('>') {
+        (metric, Comparison::Above, threshold)
+    } else if let Some((metric, threshold)) = condition.split_once('<') {
+        (metric, Comparison::Below, threshold)
+    } else {
+        return Err(format!("invalid alert '{}', expected e.g. 'in > 800Mbps for 10s'", value));
+    };
+
+    let metric = AlertMetric::NAMES
+        .iter()
+        .find(|(name, _)| *name == metric.trim())
+        .map(|(_, metric)| *metric)
+        .ok_or_else(|| {
+            format!(
+                "unknown metric '{}' in alert, expected one of in, out, total, transit, internal, broadcast, in_pps, out_pps",
+                metric.trim()
+            )
+        })?;
+
+    let threshold = if metric.is_packet_rate() {
+        parse_scaled(threshold, &[("", 1.0), ("pps", 1.0), ("kpps", 1e3), ("mpps", 1e6)])?
+    } else {
+        parse_rate(threshold)?
+    };
+
+    Ok(AlertRule {
+        text: value.trim().to_string(),
+        metric,
+        comparison,
+        threshold,
+        duration,
+    })
+}
+
+/// Parses a `--format` template such as `{time} {in:8} {out:8}`; `{{` and `}}` are literal braces.
+pub fn parse_format(value: &str) -> Result<LineFormat, String> {
+    let mut pieces = Vec::new();
+    let mut text = String::new();
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.as_str().starts_with('{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.as_str().starts_with('}') => {
+                chars.next();
+                text.push('}');
+            }
+            '{' => {
+                let (placeholder, rest) = chars
+                    .as_str()
+                    .split_once('}')
+                    .ok_or_else(|| format!("unclosed '{{' in format '{}'", value))?;
+                let (name, width) = match placeholder.split_once(':') {
+                    Some((name, width)) => (
+                        name,
+                        width
+                            .parse()
+                            .map_err(|_| format!("invalid width '{}' for '{}' in format", width, name))?,
+                    ),
+                    None => (placeholder, 0),
+                };
+                let field = OutputField::NAMES
+                    .iter()
+                    .find(|(field_name, _)| *field_name == name)
+                    .map(|(_, field)| *field)
+                    .ok_or_else(|| {
+                        let names: Vec<&str> = OutputField::NAMES.iter().map(|(name, _)| *name).collect();
+                        format!("unknown field '{}' in format, expected one of {}", name, names.join(", "))
+                    })?;
+
+                if !text.is_empty() {
+                    pieces.push(FormatPiece::Text(std::mem::take(&mut text)));
+                }
+                pieces.push(FormatPiece::Field(field, width));
+                chars = rest.chars();
+            }
+            '}' => return Err(format!("unmatched '}}' in format '{}', write '}}}}' for a literal one", value)),
+            c => text.push(c),
+        }
+    }
+    if !text.is_empty() {
+        pieces.push(FormatPiece::Text(text));
+    }
+
+    Ok(LineFormat { pieces })
+}
+
+/// Parses a bit rate such as `800Mbps`, `1.5Gbps` or `10G` (a bare number is bit/s) into bytes/s.
+pub fn parse_rate(value: &str) -> Result<f64, String> {
+    // Rates are written in bits, the calculator works in bytes
+    let bits = parse_scaled(
+        value,
+        &[
+            ("", 1.0),
+            ("bps", 1.0),
+            ("k", 1e3),
+            ("kbps", 1e3),
+            ("m", 1e6),
+            ("mbps", 1e6),
+            ("g", 1e9),
+            ("gbps", 1e9),
+            ("t", 1e12),
+            ("tbps", 1e12),
+        ],
+    )?;
+    Ok(bits / 8.0)
+}
+
+// A number followed by one of `units` (matched case-insensitively), scaled by that unit
+fn parse_scaled(value: &str, units: &[(&str, f64)]) -> Result<f64, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+
+    let unit = unit.trim().to_ascii_lowercase();
+    let scale = units
+        .iter()
+        .find(|(name, _)| *name == unit)
+        .map(|(_, scale)| *scale)
+        .ok_or_else(|| {
+            let names: Vec<&str> = units.iter().map(|(name, _)| *name).filter(|name| !name.is_empty()).collect();
+            format!("unknown unit in '{}', expected {}", value, names.join(", "))
+        })?;
+    let number: f64 = number.parse().map_err(|_| format!("invalid number in '{}'", value))?;
+    Ok(number * scale)
+}
+
+fn default_quota_state(interface: &str) -> PathBuf {
+    let state_dir = std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))
+        .unwrap_or_default();
+    state_dir.join("tcpgraph").join(format!("quota-{}", interface))
+}
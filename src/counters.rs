@@ -0,0 +1,217 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+const PROC_NET_DEV: &str = "/proc/net/dev";
+
+/// Where the rates come from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum TrafficSource {
+    /// Packet capture with the BPF filter (needs root or CAP_NET_RAW)
+    Pcap,
+    /// Deltas of the kernel's interface counters; no filter, no privileges
+    Counters,
+}
+
+impl TrafficSource {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TrafficSource::Pcap => "packet capture",
+            TrafficSource::Counters => "kernel interface counters (/proc/net/dev)",
+        }
+    }
+}
+
+/// One interface's row of `/proc/net/dev`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InterfaceCounters {
+    pub rx_bytes: u64,
+    pub rx_packets: u64,
+    pub rx_dropped: u64,
+    pub tx_bytes: u64,
+    pub tx_packets: u64,
+    pub tx_dropped: u64,
+}
+
+impl InterfaceCounters {
+    /// Every interface in the contents of `/proc/net/dev`, in file order.
+    pub fn parse_all(contents: &str) -> Vec<(String, Self)> {
+        contents
+            .lines()
+            .filter_map(|line| {
+                let (name, fields) = line.split_once(':')?;
+                let fields: Vec<u64> = fields
+                    .split_whitespace()
+                    .map(str::parse)
+                    .collect::<Result<_, _>>()
+                    .ok()?;
+
+                // Receive: bytes packets errs drop fifo frame compressed multicast, then the same for transmit
+                (fields.len() >= 16).then(|| {
+                    let counters = InterfaceCounters {
+                        rx_bytes: fields[0],
+                        rx_packets: fields[1],
+                        rx_dropped: fields[3],
+                        tx_bytes: fields[8],
+                        tx_packets: fields[9],
+                        tx_dropped: fields[11],
+                    };
+                    (name.trim().to_string(), counters)
+                })
+            })
+            .collect()
+    }
+
+    /// Finds `interface` in the contents of `/proc/net/dev`.
+    pub fn parse(contents: &str, interface: &str) -> Option<Self> {
+        Self::parse_all(contents)
+            .into_iter()
+            .find_map(|(name, counters)| (name == interface).then_some(counters))
+    }
+
+    /// Counters of `interface`, or for "any" of every interface added up except loopback and
+    /// those `is_member` says belong to a bridge or bond, whose traffic the master counts again.
+    /// Stacked interfaces without a master (VLANs, tunnels) are still counted twice.
+    pub fn parse_total(contents: &str, interface: &str, is_member: impl Fn(&str) -> bool) -> Option<Self> {
+        if interface != "any" {
+            return Self::parse(contents, interface);
+        }
+        Some(
+            Self::parse_all(contents)
+                .into_iter()
+                .filter(|(name, _)| name != "lo" && !is_member(name))
+                .fold(Self::default(), |total, (_, counters)| InterfaceCounters {
+                    rx_bytes: total.rx_bytes + counters.rx_bytes,
+                    rx_packets: total.rx_packets + counters.rx_packets,
+                    rx_dropped: total.rx_dropped + counters.rx_dropped,
+                    tx_bytes: total.tx_bytes + counters.tx_bytes,
+                    tx_packets: total.tx_packets + counters.tx_packets,
+                    tx_dropped: total.tx_dropped + counters.tx_dropped,
+                }),
+        )
+    }
+
+    /// Whether `interface` is enslaved to a bridge or bond, going by `/sys/class/net/<interface>/master`.
+    pub fn has_master(interface: &str) -> bool {
+        Path::new("/sys/class/net").join(interface).join("master").exists()
+    }
+
+    pub fn read(interface: &str) -> Option<Self> {
+        Self::parse(&fs::read_to_string(PROC_NET_DEV).ok()?, interface)
+    }
+
+    /// What was counted since `earlier`; a counter that went backwards (interface reset) counts as zero.
+    pub fn since(&self, earlier: &InterfaceCounters) -> Self {
+        InterfaceCounters {
+            rx_bytes: self.rx_bytes.saturating_sub(earlier.rx_bytes),
+            rx_packets: self.rx_packets.saturating_sub(earlier.rx_packets),
+            rx_dropped: self.rx_dropped.saturating_sub(earlier.rx_dropped),
+            tx_bytes: self.tx_bytes.saturating_sub(earlier.tx_bytes),
+            tx_packets: self.tx_packets.saturating_sub(earlier.tx_packets),
+            tx_dropped: self.tx_dropped.saturating_sub(earlier.tx_dropped),
+        }
+    }
+}
+
+/// Feeds the calculator from the interface counters instead of captured packets.
+pub struct CounterSource {
+    interface: String,
+    last: InterfaceCounters,
+}
+
+impl CounterSource {
+    /// Takes the first reading, so the first interval already has a rate.
+    pub fn open(interface: &str) -> Result<Self> {
+        let contents = fs::read_to_string(PROC_NET_DEV).with_context(|| format!("Failed to read {}", PROC_NET_DEV))?;
+        let last = InterfaceCounters::parse_total(&contents, interface, InterfaceCounters::has_master)
+            .with_context(|| format!("Interface '{}' not found in {}", interface, PROC_NET_DEV))?;
+        Ok(Self::new(interface.to_string(), last))
+    }
+
+    pub fn new(interface: String, last: InterfaceCounters) -> Self {
+        Self { interface, last }
+    }
+
+    /// Traffic since the previous poll; `None` if the interface went away.
+    pub fn poll(&mut self) -> Option<InterfaceCounters> {
+        let contents = fs::read_to_string(PROC_NET_DEV).ok()?;
+        Some(self.observe(InterfaceCounters::parse_total(&contents, &self.interface, InterfaceCounters::has_master)?))
+    }
+
+    pub fn observe(&mut self, counters: InterfaceCounters) -> InterfaceCounters {
+        let delta = counters.since(&self.last);
+        self.last = counters;
+        delta
+    }
+}
+
+/// What the kernel saw on the interface, next to what the capture saw.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KernelComparison {
+    /// Kernel rates since the previous sample, bytes/s
+    pub inbound: f64,
+    pub outbound: f64,
+    /// Share of the packets the kernel received and sent that the capture counted, in any
+    /// direction, since the first sample, in percent
+    pub matched: Option<f64>,
+    /// Packets the interface dropped since the first sample: NIC ring overruns on receive,
+    /// qdisc drops on transmit. Packets lost by the capture itself are not among them.
+    pub rx_dropped: u64,
+    pub tx_dropped: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct CounterSample {
+    time: SystemTime,
+    counters: InterfaceCounters,
+    captured_packets: u64,
+}
+
+/// Samples the interface counters once per interval and compares them with the capture.
+pub struct CounterSampler {
+    interface: String,
+    first: Option<CounterSample>,
+    last: Option<CounterSample>,
+}
+
+impl CounterSampler {
+    /// `None` if the interface has no row in `/proc/net/dev` (e.g. "any", or not on Linux).
+    pub fn for_interface(interface: &str) -> Option<Self> {
+        InterfaceCounters::read(interface)?;
+        Some(Self::new(interface.to_string()))
+    }
+
+    pub fn new(interface: String) -> Self {
+        Self { interface, first: None, last: None }
+    }
+
+    /// `captured_packets` counts every packet the capture delivered since start.
+    pub fn sample(&mut self, now: SystemTime, captured_packets: u64) -> Option<KernelComparison> {
+        let counters = InterfaceCounters::read(&self.interface)?;
+        self.observe(now, counters, captured_packets)
+    }
+
+    /// Feeds one reading of the counters; the first one only sets the baseline.
+    pub fn observe(&mut self, now: SystemTime, counters: InterfaceCounters, captured_packets: u64) -> Option<KernelComparison> {
+        let sample = CounterSample { time: now, counters, captured_packets };
+        let first = *self.first.get_or_insert(sample);
+        let last = self.last.replace(sample)?;
+
+        let seconds = now.duration_since(last.time).unwrap_or_default().as_secs_f64();
+        let rate = |current: u64, previous: u64| {
+            if seconds > 0.0 { current.saturating_sub(previous) as f64 / seconds } else { 0.0 }
+        };
+        let kernel_packets = |counters: InterfaceCounters| counters.rx_packets + counters.tx_packets;
+        let kernel = kernel_packets(counters).saturating_sub(kernel_packets(first.counters));
+        let captured = captured_packets.saturating_sub(first.captured_packets);
+
+        Some(KernelComparison {
+            inbound: rate(counters.rx_bytes, last.counters.rx_bytes),
+            outbound: rate(counters.tx_bytes, last.counters.tx_bytes),
+            matched: (kernel > 0).then(|| captured as f64 / kernel as f64 * 100.0),
+            rx_dropped: counters.rx_dropped.saturating_sub(first.counters.rx_dropped),
+            tx_dropped: counters.tx_dropped.saturating_sub(first.counters.tx_dropped),
+        })
+    }
+}
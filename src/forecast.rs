@@ -0,0 +1,53 @@
+use std::time::Duration;
+
+// Holt's linear method: how quickly the level and the trend follow new samples
+const LEVEL_SMOOTHING: f64 = 0.3;
+const TREND_SMOOTHING: f64 = 0.1;
+
+/// A trend needs a few points before it means anything
+pub const MIN_FORECAST_SAMPLES: usize = 10;
+
+/// Level and trend of a rate series, fitted with Holt's linear (double exponential) smoothing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Forecast {
+    /// Smoothed rate at the latest sample, bytes/s
+    pub level: f64,
+    /// Change of the rate per interval, bytes/s
+    pub trend: f64,
+}
+
+impl Forecast {
+    /// Fits the samples, oldest first; `None` if there are too few of them.
+    pub fn fit(samples: impl IntoIterator<Item = f64>) -> Option<Self> {
+        let mut samples = samples.into_iter();
+        let mut forecast = Forecast { level: samples.next()?, trend: 0.0 };
+        let mut count = 1;
+
+        for sample in samples {
+            let previous_level = forecast.level;
+            forecast.level = LEVEL_SMOOTHING * sample + (1.0 - LEVEL_SMOOTHING) * (previous_level + forecast.trend);
+            forecast.trend =
+                TREND_SMOOTHING * (forecast.level - previous_level) + (1.0 - TREND_SMOOTHING) * forecast.trend;
+            count += 1;
+        }
+
+        (count >= MIN_FORECAST_SAMPLES).then_some(forecast)
+    }
+
+    /// Rate `steps` intervals after one of `start`, following the trend; the chart starts
+    /// from its last point, which is smoothed differently than the fitted level.
+    pub fn project(&self, start: f64, steps: f64) -> f64 {
+        (start + self.trend * steps).max(0.0)
+    }
+
+    /// How long until the projected rate reaches `rate`, if it is heading there at all.
+    pub fn time_to_reach(&self, rate: f64, update_interval: Duration) -> Option<Duration> {
+        if self.level >= rate {
+            return Some(Duration::ZERO);
+        }
+        if self.trend <= 0.0 {
+            return None;
+        }
+        Duration::try_from_secs_f64((rate - self.level) / self.trend * update_interval.as_secs_f64()).ok()
+    }
+}
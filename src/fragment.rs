@@ -0,0 +1,172 @@
+use crate::capture::FlowKey;
+use pnet::packet::ip::IpNextHeaderProtocol;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime};
+
+// Same reassembly timeout the Linux kernel uses by default (net.ipv4.ipfrag_time)
+const REASSEMBLY_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_PENDING_DATAGRAMS: usize = 4096;
+const EXPIRY_SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+// How long a reassembled datagram is remembered, so that late duplicates of its fragments are recognised
+const COMPLETED_RETENTION: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FragmentKey {
+    pub src: IpAddr,
+    pub dst: IpAddr,
+    pub protocol: IpNextHeaderProtocol,
+    pub identification: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct IpFragment {
+    pub key: FragmentKey,
+    pub offset: u32,
+    pub more_fragments: bool,
+    pub length: u32,
+}
+
+impl IpFragment {
+    pub fn is_first(&self) -> bool {
+        self.offset == 0
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FragmentStats {
+    pub fragmented_datagrams: u64,
+    pub reassembly_timeouts: u64,
+    /// Fragments left untracked because too many datagrams were pending
+    pub overflow_drops: u64,
+}
+
+#[derive(Debug)]
+struct PendingDatagram {
+    flow: Option<FlowKey>,
+    first_seen: SystemTime,
+    /// Byte ranges received so far, sorted and merged
+    received: Vec<(u32, u32)>,
+    total_length: Option<u32>,
+}
+
+impl PendingDatagram {
+    fn add_range(&mut self, start: u32, end: u32) {
+        let (mut start, mut end) = (start, end);
+        self.received.retain(|&(other_start, other_end)| {
+            let overlaps = other_start <= end && start <= other_end;
+            if overlaps {
+                start = start.min(other_start);
+                end = end.max(other_end);
+            }
+            !overlaps
+        });
+        let index = self.received.partition_point(|&(other_start, _)| other_start < start);
+        self.received.insert(index, (start, end));
+    }
+
+    // Duplicates and overlaps don't count twice, so only full coverage completes the datagram
+    fn is_complete(&self) -> bool {
+        self.total_length.is_some_and(|total| self.received == [(0, total)])
+    }
+}
+
+#[derive(Debug)]
+struct CompletedDatagram {
+    flow: Option<FlowKey>,
+    completed_at: SystemTime,
+}
+
+/// Remembers which flow each fragmented datagram belongs to so that non-first
+/// fragments, which carry no transport header, can be attributed to it.
+pub struct FragmentTracker {
+    datagrams: HashMap<FragmentKey, PendingDatagram>,
+    completed: HashMap<FragmentKey, CompletedDatagram>,
+    last_sweep: SystemTime,
+}
+
+impl Default for FragmentTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FragmentTracker {
+    pub fn new() -> Self {
+        Self {
+            datagrams: HashMap::new(),
+            completed: HashMap::new(),
+            last_sweep: SystemTime::UNIX_EPOCH,
+        }
+    }
+
+    /// Records a fragment and returns the flow of its datagram (if the first
+    /// fragment has been seen) along with any newly fragmented datagram.
+    pub fn observe(
+        &mut self,
+        fragment: &IpFragment,
+        first_fragment_flow: Option<FlowKey>,
+        now: SystemTime,
+    ) -> (Option<FlowKey>, FragmentStats) {
+        let mut stats = FragmentStats::default();
+
+        // A retransmitted or duplicated fragment of a datagram that is already complete
+        if let Some(completed) = self.completed.get(&fragment.key) {
+            return (completed.flow, stats);
+        }
+
+        if !self.datagrams.contains_key(&fragment.key) {
+            if self.datagrams.len() >= MAX_PENDING_DATAGRAMS {
+                stats.overflow_drops = 1;
+                return (first_fragment_flow, stats);
+            }
+            stats.fragmented_datagrams = 1;
+        }
+
+        let datagram = self.datagrams.entry(fragment.key).or_insert(PendingDatagram {
+            flow: None,
+            first_seen: now,
+            received: Vec::new(),
+            total_length: None,
+        });
+
+        if fragment.is_first() {
+            datagram.flow = first_fragment_flow;
+        }
+        datagram.add_range(fragment.offset, fragment.offset + fragment.length);
+        if !fragment.more_fragments {
+            datagram.total_length = Some(fragment.offset + fragment.length);
+        }
+
+        let flow = datagram.flow;
+        if datagram.is_complete() {
+            self.datagrams.remove(&fragment.key);
+            if self.completed.len() < MAX_PENDING_DATAGRAMS {
+                self.completed.insert(fragment.key, CompletedDatagram { flow, completed_at: now });
+            }
+        }
+
+        (flow, stats)
+    }
+
+    /// Drops datagrams whose fragments never all arrived and returns how many
+    /// timed out. Sweeps at most once per second; called for every fragment and
+    /// once per interval, so that timeouts are reported even when traffic stops.
+    pub fn expire(&mut self, now: SystemTime) -> u64 {
+        if now.duration_since(self.last_sweep).unwrap_or_default() < EXPIRY_SWEEP_INTERVAL {
+            return 0;
+        }
+        self.last_sweep = now;
+
+        let before = self.datagrams.len();
+        self.datagrams.retain(|_, datagram| {
+            now.duration_since(datagram.first_seen).unwrap_or_default() < REASSEMBLY_TIMEOUT
+        });
+
+        self.completed.retain(|_, datagram| {
+            now.duration_since(datagram.completed_at).unwrap_or_default() < COMPLETED_RETENTION
+        });
+
+        (before - self.datagrams.len()) as u64
+    }
+}
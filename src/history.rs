@@ -0,0 +1,147 @@
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// One retention level: points `resolution` apart, kept for `span`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetentionTier {
+    pub resolution: Duration,
+    pub span: Duration,
+}
+
+impl RetentionTier {
+    pub fn capacity(&self) -> usize {
+        (self.span.as_secs_f64() / self.resolution.as_secs_f64()).ceil().max(1.0) as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RateRange {
+    pub min: f64,
+    pub avg: f64,
+    pub max: f64,
+}
+
+/// Rates in bytes/s consolidated over one tier slot, stamped with the slot start.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HistoryPoint {
+    pub timestamp: SystemTime,
+    pub inbound: RateRange,
+    pub outbound: RateRange,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RangeAccumulator {
+    min: f64,
+    max: f64,
+    sum: f64,
+}
+
+impl RangeAccumulator {
+    fn new(rate: f64) -> Self {
+        Self { min: rate, max: rate, sum: rate }
+    }
+
+    fn add(&mut self, rate: f64) {
+        self.min = self.min.min(rate);
+        self.max = self.max.max(rate);
+        self.sum += rate;
+    }
+
+    fn consolidate(&self, samples: u64) -> RateRange {
+        RateRange {
+            min: self.min,
+            avg: self.sum / samples as f64,
+            max: self.max,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PendingPoint {
+    slot: u128,
+    samples: u64,
+    inbound: RangeAccumulator,
+    outbound: RangeAccumulator,
+}
+
+pub struct HistoryTier {
+    pub retention: RetentionTier,
+    points: VecDeque<HistoryPoint>,
+    pending: Option<PendingPoint>,
+}
+
+impl HistoryTier {
+    /// Completed points, oldest first. The slot still being filled is not included.
+    pub fn points(&self) -> &VecDeque<HistoryPoint> {
+        &self.points
+    }
+
+    fn record(&mut self, timestamp: SystemTime, inbound: f64, outbound: f64) {
+        // Slots are aligned to the epoch so every tier's boundaries line up with the clock
+        let since_epoch = timestamp.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let slot = since_epoch.as_nanos() / self.retention.resolution.as_nanos().max(1);
+
+        match &mut self.pending {
+            Some(pending) if pending.slot == slot => {
+                pending.samples += 1;
+                pending.inbound.add(inbound);
+                pending.outbound.add(outbound);
+            }
+            _ => {
+                if let Some(pending) = self.pending.take() {
+                    self.push(pending);
+                }
+                self.pending = Some(PendingPoint {
+                    slot,
+                    samples: 1,
+                    inbound: RangeAccumulator::new(inbound),
+                    outbound: RangeAccumulator::new(outbound),
+                });
+            }
+        }
+    }
+
+    fn push(&mut self, pending: PendingPoint) {
+        let slot_start = self.retention.resolution.as_nanos() * pending.slot;
+        self.points.push_back(HistoryPoint {
+            timestamp: UNIX_EPOCH + Duration::from_nanos(slot_start as u64),
+            inbound: pending.inbound.consolidate(pending.samples),
+            outbound: pending.outbound.consolidate(pending.samples),
+        });
+        while self.points.len() > self.retention.capacity() {
+            self.points.pop_front();
+        }
+    }
+}
+
+/// Round-robin style history: every per-interval rate is folded into each tier,
+/// which keeps min/avg/max per slot and a fixed number of slots, so memory is
+/// bounded no matter how long tcpgraph runs.
+pub struct TieredHistory {
+    tiers: Vec<HistoryTier>,
+}
+
+impl TieredHistory {
+    pub fn new(retention: &[RetentionTier]) -> Self {
+        Self {
+            tiers: retention
+                .iter()
+                .map(|tier| HistoryTier {
+                    retention: *tier,
+                    points: VecDeque::new(),
+                    pending: None,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn record(&mut self, timestamp: SystemTime, inbound: f64, outbound: f64) {
+        for tier in &mut self.tiers {
+            tier.record(timestamp, inbound, outbound);
+        }
+    }
+
+    pub fn tiers(&self) -> &[HistoryTier] {
+        &self.tiers
+    }
+}
@@ -1,4 +1,14 @@
 pub mod cli;
 pub mod capture;
 pub mod bandwidth;
-pub mod ui;
\ No newline at end of file
+pub mod ui;
+pub mod tcp_window;
+pub mod fragment;
+pub mod history;
+pub mod quota;
+pub mod calendar;
+pub mod anomaly;
+pub mod alert;
+pub mod forecast;
+pub mod counters;
+pub mod output;
\ No newline at end of file
@@ -2,13 +2,31 @@ mod cli;
 mod capture;
 mod bandwidth;
 mod ui;
+mod tcp_window;
+mod fragment;
+mod history;
+mod quota;
+mod calendar;
+mod anomaly;
+mod alert;
+mod forecast;
+mod counters;
+mod output;
 
 use anyhow::{Context, Result};
 use cli::Args;
-use capture::PacketCapture;
-use bandwidth::start_bandwidth_monitor;
-use ui::{App, run_ui};
-use std::time::Duration;
+use capture::{AccountingLayer, MAX_SERIES, PacketCapture, TrafficDirection};
+use counters::{CounterSampler, CounterSource, TrafficSource};
+use alert::AlertEngine;
+use anomaly::{AnomalyDetector, BaselineMethod, MIN_BASELINE_SAMPLES};
+use bandwidth::{BandwidthCalculator, PERCENTILE_ERROR_PERCENT, start_bandwidth_monitor};
+use output::run_headless;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, mpsc};
+use ui::{App, format_bytes, format_summary, format_transfer, run_ui};
+use quota::QuotaTracker;
+use std::time::{Duration, SystemTime};
 use tokio::signal;
 use pcap;
 
@@ -18,48 +36,331 @@ async fn main() -> Result<()> {
     
     validate_args(&args)?;
     
-    println!("Starting tcpgraph...");
-    println!("Interface: {}", args.interface);
-    println!("Filter: {}", args.filter);
-    println!("Update interval: {}s", args.interval);
+    // Settings go to stderr, so that stdout carries only the --no-tui lines
+    eprintln!("Starting tcpgraph...");
+    eprintln!("Interface: {}", args.interface);
+    eprintln!("Source: {}", args.source.name());
+    if args.source == TrafficSource::Pcap {
+        eprintln!("Filter: {}", args.filter);
+    }
+    eprintln!("Update interval: {:?}", args.interval);
+    eprintln!("Averaging window: {:?}", args.window());
+    eprintln!("Smoothing: {}", args.smoothing().describe());
+    if args.source == TrafficSource::Pcap {
+        eprintln!("Accounting layer: {}", args.accounting_layer().name());
+    }
+    eprintln!("Total: {}", total_label(&args.total));
+    if let Some(horizon) = args.forecast {
+        eprintln!("Forecast: {:?} ahead", horizon);
+    }
+    let link_speed = args.link_speed.or_else(|| PacketCapture::link_speed(&args.interface));
+    match (link_speed, args.link_speed.is_some()) {
+        (Some(speed), true) => eprintln!("Link speed: {:.0} Mbps", speed * 8.0 / 1_000_000.0),
+        (Some(speed), false) => eprintln!("Link speed: {:.0} Mbps (detected)", speed * 8.0 / 1_000_000.0),
+        (None, _) => eprintln!("Link speed: unknown (set --link-speed for utilization)"),
+    }
+    let kernel_counters = match args.source {
+        TrafficSource::Pcap => {
+            let sampler = CounterSampler::for_interface(&args.interface);
+            match sampler {
+                Some(_) => eprintln!("Kernel counters: /proc/net/dev"),
+                None => eprintln!("Kernel counters: unavailable for {}", args.interface),
+            }
+            sampler
+        }
+        TrafficSource::Counters => None,
+    };
+    for series in &args.series {
+        eprintln!("Series: {} = {}", series.name, series.filter);
+    }
     
     if let Some(duration) = args.duration {
-        println!("Duration: {}s", duration);
+        eprintln!("Duration: {}s", duration);
+    }
+
+    let quota = match args.quota_config() {
+        Some(config) => {
+            eprintln!(
+                "Quota: {} per {} ({}), state in {}",
+                format_bytes(config.limit_bytes),
+                config.period.name(),
+                config.direction.name(),
+                config.state_file.display()
+            );
+            Some(QuotaTracker::load(config, SystemTime::now())?)
+        }
+        None => None,
+    };
+
+    let anomaly_detector = match args.anomaly {
+        BaselineMethod::Off => None,
+        method => {
+            eprintln!(
+                "Anomaly detection: {} over the last {} intervals, beyond {}σ",
+                method.name(),
+                args.history,
+                args.anomaly_sigma
+            );
+            if args.history < MIN_BASELINE_SAMPLES {
+                eprintln!("  (needs --history of at least {} to have a baseline)", MIN_BASELINE_SAMPLES);
+            }
+            let detector = AnomalyDetector::new(method, args.anomaly_sigma);
+            Some(match &args.anomaly_log {
+                Some(path) => detector.with_log(path)?,
+                None => detector,
+            })
+        }
+    };
+
+    for rule in &args.alert {
+        eprintln!("Alert: {}", rule.text);
+    }
+    if let Some(command) = &args.alert_command {
+        eprintln!("Alert command: {}", command);
     }
 
-    let packet_capture = PacketCapture::new(args.interface.clone(), args.filter.clone(), args.payload_only);
+    let (packet_rx, counter_source, packet_capture) = match args.source {
+        TrafficSource::Pcap => {
+            let packet_capture = PacketCapture::new(args.interface.clone(), args.filter.clone(), args.accounting_layer())
+                .with_series(args.series.clone());
+            let packet_rx = packet_capture.start_capture().await
+                .context("Failed to start packet capture")?;
+            (packet_rx, None, Some(packet_capture))
+        }
+        // Nothing is captured: the sender is dropped right away and the calculator polls the counters instead
+        TrafficSource::Counters => (mpsc::channel().1, Some(CounterSource::open(&args.interface)?), None),
+    };
     
-    let packet_rx = packet_capture.start_capture().await
-        .context("Failed to start packet capture")?;
+    let update_interval = args.interval;
+    let window_duration = args.window();
+    let mut calculator = BandwidthCalculator::new(window_duration, args.history, 1)
+        .with_update_interval(update_interval)
+        .with_measured_spans()
+        .with_smoothing(args.smoothing())
+        .with_total(args.total.clone())
+        .with_series(args.series.len());
+    if args.forecast.is_some() {
+        calculator = calculator.with_forecast();
+    }
+    if let Some(quota) = quota {
+        calculator = calculator.with_quota(quota);
+    }
+    if let Some(detector) = anomaly_detector {
+        calculator = calculator.with_anomaly_detector(detector);
+    }
+    if let Some(sampler) = kernel_counters {
+        calculator = calculator.with_kernel_counters(sampler);
+    }
+    if let Some(source) = counter_source {
+        calculator = calculator.with_counter_source(source);
+    }
+    if let Some(capture) = &packet_capture {
+        calculator = calculator
+            .with_fragment_tracker(capture.fragment_tracker())
+            .with_capture_drops(capture.capture_drops());
+    }
+    if !args.alert.is_empty() {
+        let mut alerts = AlertEngine::new(args.alert.clone(), args.alert_hysteresis);
+        if let Some(command) = &args.alert_command {
+            alerts = alerts.with_command(command.clone(), args.interface.clone());
+        }
+        calculator = calculator.with_alerts(alerts);
+    }
     
-    let update_interval = Duration::from_secs(args.interval);
-    let bandwidth_rx = start_bandwidth_monitor(packet_rx, update_interval, args.smoothing).await;
+    let (command_tx, command_rx) = mpsc::channel();
+    let (bandwidth_rx, monitor) = start_bandwidth_monitor(packet_rx, command_rx, calculator).await;
     
-    let app = App::new(args.interface, args.filter);
+    let filter_label = match args.source {
+        TrafficSource::Pcap => args.filter,
+        TrafficSource::Counters => "none (kernel counters)".to_string(),
+    };
+    let mut app = App::new(
+        args.interface,
+        filter_label,
+        args.points,
+        total_label(&args.total),
+        args.series.iter().map(|series| series.name.clone()).collect(),
+        &args.retention,
+    )
+    .with_link_speed(link_speed);
+    if let Some(horizon) = args.forecast {
+        app = app.with_forecast(horizon, update_interval);
+    }
+    if args.source == TrafficSource::Counters {
+        app = app.without_packet_details();
+    }
     
-    tokio::select! {
-        result = tokio::task::spawn_blocking(move || run_ui(app, bandwidth_rx, update_interval)) => {
-            result??;
+    let no_tui = args.no_tui;
+    let format = args.format;
+    let duration = args.duration.map(Duration::from_secs);
+    let stop = Arc::new(AtomicBool::new(false));
+    let run_stop = Arc::clone(&stop);
+    let run = move || {
+        if no_tui {
+            run_headless(app, bandwidth_rx, &format, duration, run_stop)
+        } else {
+            run_ui(app, bandwidth_rx, command_tx, update_interval, run_stop)
         }
+    };
+
+    let mut handle = tokio::task::spawn_blocking(run);
+    let result = tokio::select! {
+        result = &mut handle => result,
         _ = signal::ctrl_c() => {
-            println!("\nReceived Ctrl+C, shutting down gracefully...");
+            // stderr, like everything but the --no-tui lines
+            eprintln!("\nReceived Ctrl+C, shutting down gracefully...");
+            // Let the UI restore the terminal and hand the app back for the summary
+            stop.store(true, Ordering::Relaxed);
+            handle.await
         }
+    };
+
+    // Saves the quota state, also when the UI failed
+    let stopped = monitor.stop().await;
+    let app = result??;
+    if no_tui {
+        print_session_summary(&mut io::stderr(), &app, update_interval)?;
+    } else {
+        print_session_summary(&mut io::stdout(), &app, update_interval)?;
     }
 
+    stopped
+}
+
+fn print_session_summary(out: &mut impl Write, app: &App, update_interval: Duration) -> io::Result<()> {
+    writeln!(
+        out,
+        "Session summary ({} intervals of {:?}), mean/p50/p90/p95/p99 in Mbps (percentiles within ±{}%):",
+        app.inbound_summary.samples, update_interval, PERCENTILE_ERROR_PERCENT
+    )?;
+    writeln!(out, "  Inbound:  {}", format_summary(&app.inbound_summary))?;
+    writeln!(out, "  Outbound: {}", format_summary(&app.outbound_summary))?;
+    writeln!(
+        out,
+        "Transferred: in {}, out {}",
+        format_transfer(app.session_totals.inbound_bytes, app.session_totals.inbound_packets),
+        format_transfer(app.session_totals.outbound_bytes, app.session_totals.outbound_packets),
+    )?;
+    if let Some(quota) = &app.quota {
+        writeln!(
+            out,
+            "Quota: {} of {} used this {} ({:.1}%)",
+            format_bytes(quota.used_bytes),
+            format_bytes(quota.limit_bytes),
+            quota.period.name(),
+            quota.used_percent()
+        )?;
+    }
+    if let Some(alerts) = &app.alerts {
+        writeln!(out, "Alerts fired: {}, still firing: {}", app.alerts_fired, alerts.firing.len())?;
+    }
+    if app.anomaly_count > 0 {
+        writeln!(out, "Anomalies: {} (latest {} below)", app.anomaly_count, app.recent_anomalies.len())?;
+        for anomaly in &app.recent_anomalies {
+            writeln!(out, "  {}", anomaly.describe())?;
+        }
+    }
     Ok(())
 }
 
+fn total_label(directions: &[TrafficDirection]) -> String {
+    directions
+        .iter()
+        .map(|direction| direction.name())
+        .collect::<Vec<_>>()
+        .join("+")
+}
+
 fn validate_args(args: &Args) -> Result<()> {
     if args.interface.is_empty() {
         anyhow::bail!("Interface name cannot be empty");
     }
     
-    if args.filter.is_empty() {
-        anyhow::bail!("Filter expression cannot be empty");
+    match args.source {
+        TrafficSource::Pcap if args.filter.is_empty() => {
+            anyhow::bail!("Filter expression cannot be empty");
+        }
+        TrafficSource::Counters if !args.filter.is_empty() || !args.series.is_empty() || args.payload_only => {
+            anyhow::bail!("--filter, --series and --payload-only need packet capture (--source pcap)");
+        }
+        TrafficSource::Counters if args.accounting_layer() != AccountingLayer::Frame => {
+            anyhow::bail!("--layer {} needs packet capture (--source pcap); the kernel counters count whole frames", args.accounting_layer().name());
+        }
+        _ => {}
+    }
+    
+    if args.interval < Duration::from_millis(1) {
+        anyhow::bail!("Update interval must be at least 1ms");
+    }
+    
+    if args.window() < args.interval {
+        anyhow::bail!("Averaging window must not be shorter than the update interval");
+    }
+    
+    if args.smoothing == 0 {
+        anyhow::bail!("Smoothing samples must be greater than 0");
+    }
+    
+    if !(args.ewma_alpha > 0.0 && args.ewma_alpha <= 1.0) {
+        anyhow::bail!("EWMA alpha must be greater than 0 and at most 1");
+    }
+    
+    if args.half_life.is_some_and(|half_life| half_life <= 0.0) {
+        anyhow::bail!("EWMA half-life must be greater than 0");
+    }
+    
+    if args.total.is_empty() {
+        anyhow::bail!("Total must include at least one direction");
+    }
+    
+    if args.anomaly_sigma <= 0.0 {
+        anyhow::bail!("Anomaly threshold must be greater than 0");
+    }
+    
+    if args.anomaly_log.is_some() && args.anomaly == BaselineMethod::Off {
+        anyhow::bail!("--anomaly-log needs --anomaly mad or --anomaly mean");
     }
     
-    if args.interval == 0 {
-        anyhow::bail!("Update interval must be greater than 0");
+    if !(args.alert_hysteresis >= 0.0 && args.alert_hysteresis < 100.0) {
+        anyhow::bail!("Alert hysteresis must be at least 0 and less than 100 percent");
+    }
+    
+    if args.alert_command.is_some() && args.alert.is_empty() {
+        anyhow::bail!("--alert-command needs at least one --alert rule");
+    }
+    
+    if args.quota_warn.iter().any(|percent| !(*percent > 0.0 && *percent <= 100.0)) {
+        anyhow::bail!("Quota warning percentages must be greater than 0 and at most 100");
+    }
+    
+    if args.series.len() > MAX_SERIES {
+        anyhow::bail!("At most {} series can be graphed", MAX_SERIES);
+    }
+    
+    for (index, series) in args.series.iter().enumerate() {
+        if args.series[..index].iter().any(|other| other.name == series.name) {
+            anyhow::bail!("Series name '{}' is used more than once", series.name);
+        }
+        // Catch typos before the UI takes over the terminal; the capture compiles them again for its link type
+        pcap::Capture::dead(pcap::Linktype::ETHERNET)?
+            .compile(&series.filter, true)
+            .with_context(|| format!("Invalid filter for series '{}'", series.name))?;
+    }
+    
+    if args.forecast.is_some_and(|horizon| horizon.is_zero()) {
+        anyhow::bail!("Forecast horizon must be greater than 0");
+    }
+    
+    if args.link_speed.is_some_and(|speed| speed <= 0.0) {
+        anyhow::bail!("Link speed must be greater than 0");
+    }
+    
+    if args.history == 0 {
+        anyhow::bail!("History length must be greater than 0");
+    }
+    
+    if args.points < 2 {
+        anyhow::bail!("Visible points must be at least 2");
     }
     
     if let Some(duration) = args.duration {
@@ -68,8 +369,10 @@ fn validate_args(args: &Args) -> Result<()> {
         }
     }
     
-    // Validate interface exists
-    validate_interface(&args.interface)?;
+    // Validate interface exists; with --source counters, /proc/net/dev is checked when it is opened
+    if args.source == TrafficSource::Pcap {
+        validate_interface(&args.interface)?;
+    }
     
     Ok(())
 }
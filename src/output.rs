@@ -0,0 +1,157 @@
+use crate::bandwidth::DirectionalBandwidth;
+use crate::calendar::format_rfc3339;
+use crate::ui::App;
+use anyhow::Result;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, mpsc};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+// How long a Ctrl+C can go unnoticed while waiting for the next interval
+const STOP_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Columns in the style of `ifstat`: time, Mbps, packets/s and bytes since start per direction
+pub const DEFAULT_FORMAT: &str =
+    "{time:20} {in:10} {out:10} {in_pps:9} {out_pps:9} {in_bytes:14} {out_bytes:14}";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputField {
+    Time,
+    Timestamp,
+    Inbound,
+    Outbound,
+    Total,
+    InboundBps,
+    OutboundBps,
+    InboundPps,
+    OutboundPps,
+    InboundBytes,
+    OutboundBytes,
+    InboundPackets,
+    OutboundPackets,
+}
+
+impl OutputField {
+    pub const NAMES: [(&'static str, OutputField); 13] = [
+        ("time", OutputField::Time),
+        ("timestamp", OutputField::Timestamp),
+        ("in", OutputField::Inbound),
+        ("out", OutputField::Outbound),
+        ("total", OutputField::Total),
+        ("in_bps", OutputField::InboundBps),
+        ("out_bps", OutputField::OutboundBps),
+        ("in_pps", OutputField::InboundPps),
+        ("out_pps", OutputField::OutboundPps),
+        ("in_bytes", OutputField::InboundBytes),
+        ("out_bytes", OutputField::OutboundBytes),
+        ("in_packets", OutputField::InboundPackets),
+        ("out_packets", OutputField::OutboundPackets),
+    ];
+
+    pub fn name(&self) -> &'static str {
+        Self::NAMES
+            .iter()
+            .find(|(_, field)| field == self)
+            .map(|(name, _)| *name)
+            .unwrap_or_default()
+    }
+
+    fn value(&self, bandwidth: &DirectionalBandwidth) -> String {
+        let mbps = |rate: f64| format!("{:.2}", rate * 8.0 / 1_000_000.0);
+        match self {
+            OutputField::Time => format_rfc3339(bandwidth.timestamp),
+            OutputField::Timestamp => bandwidth
+                .timestamp
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs()
+                .to_string(),
+            OutputField::Inbound => mbps(bandwidth.inbound),
+            OutputField::Outbound => mbps(bandwidth.outbound),
+            OutputField::Total => mbps(bandwidth.total),
+            OutputField::InboundBps => format!("{:.0}", bandwidth.inbound * 8.0),
+            OutputField::OutboundBps => format!("{:.0}", bandwidth.outbound * 8.0),
+            OutputField::InboundPps => format!("{:.0}", bandwidth.inbound_pps),
+            OutputField::OutboundPps => format!("{:.0}", bandwidth.outbound_pps),
+            OutputField::InboundBytes => bandwidth.session_totals.inbound_bytes.to_string(),
+            OutputField::OutboundBytes => bandwidth.session_totals.outbound_bytes.to_string(),
+            OutputField::InboundPackets => bandwidth.session_totals.inbound_packets.to_string(),
+            OutputField::OutboundPackets => bandwidth.session_totals.outbound_packets.to_string(),
+        }
+    }
+
+    // Numbers line up on the right, the time on the left
+    fn pad(&self, text: &str, width: usize) -> String {
+        match self {
+            OutputField::Time => format!("{:<width$}", text),
+            _ => format!("{:>width$}", text),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FormatPiece {
+    Text(String),
+    /// A `{field}` or `{field:width}` placeholder
+    Field(OutputField, usize),
+}
+
+/// A `--format` template, rendered once per interval.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineFormat {
+    pub pieces: Vec<FormatPiece>,
+}
+
+impl LineFormat {
+    /// The template with every placeholder replaced by its name, for the first line of output.
+    pub fn header(&self) -> String {
+        self.render_with(|field| field.name().to_string())
+    }
+
+    pub fn render(&self, bandwidth: &DirectionalBandwidth) -> String {
+        self.render_with(|field| field.value(bandwidth))
+    }
+
+    fn render_with(&self, text_of: impl Fn(OutputField) -> String) -> String {
+        self.pieces
+            .iter()
+            .map(|piece| match piece {
+                FormatPiece::Text(text) => text.clone(),
+                FormatPiece::Field(field, width) => field.pad(&text_of(*field), *width),
+            })
+            .collect()
+    }
+}
+
+/// Prints a header and then one line per interval to stdout until the monitor stops,
+/// `duration` has passed, `stop` is set or stdout is closed (e.g. piped into `head`).
+pub fn run_headless(
+    mut app: App,
+    bandwidth_rx: mpsc::Receiver<DirectionalBandwidth>,
+    format: &LineFormat,
+    duration: Option<Duration>,
+    stop: Arc<AtomicBool>,
+) -> Result<App> {
+    let started = Instant::now();
+    let mut stdout = io::stdout().lock();
+
+    let mut result = writeln!(stdout, "{}", format.header());
+    while result.is_ok() && !stop.load(Ordering::Relaxed) {
+        let bandwidth = match bandwidth_rx.recv_timeout(STOP_CHECK_INTERVAL) {
+            Ok(bandwidth) => bandwidth,
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        };
+        result = writeln!(stdout, "{}", format.render(&bandwidth));
+        app.update(bandwidth);
+
+        if duration.is_some_and(|duration| started.elapsed() >= duration) {
+            break;
+        }
+    }
+
+    match result {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(e.into()),
+        _ => Ok(app),
+    }
+}
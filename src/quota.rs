@@ -0,0 +1,267 @@
+use crate::calendar::{SECONDS_PER_DAY, civil_from_days, days_from_civil};
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Usage is written out at most this often, so a crash loses at most this much of it
+const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum QuotaPeriod {
+    /// Resets at midnight UTC
+    Day,
+    /// Resets on Monday at midnight UTC
+    Week,
+    /// Resets on --quota-start-day of each month at midnight UTC
+    Month,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum QuotaDirection {
+    Inbound,
+    Outbound,
+    /// Inbound and outbound combined
+    Both,
+}
+
+impl QuotaDirection {
+    pub fn name(&self) -> &'static str {
+        match self {
+            QuotaDirection::Inbound => "in",
+            QuotaDirection::Outbound => "out",
+            QuotaDirection::Both => "in+out",
+        }
+    }
+}
+
+impl QuotaPeriod {
+    pub fn name(&self) -> &'static str {
+        match self {
+            QuotaPeriod::Day => "day",
+            QuotaPeriod::Week => "week",
+            QuotaPeriod::Month => "month",
+        }
+    }
+
+    /// Start and end of the period containing `now`.
+    pub fn bounds(&self, now: SystemTime, start_day: u32) -> (SystemTime, SystemTime) {
+        let today = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() / SECONDS_PER_DAY;
+
+        let (start, end) = match self {
+            QuotaPeriod::Day => (today, today + 1),
+            QuotaPeriod::Week => {
+                // 1970-01-01 was a Thursday
+                let monday = today - (today + 3) % 7;
+                (monday, monday + 7)
+            }
+            QuotaPeriod::Month => {
+                let (year, month, day) = civil_from_days(today);
+                let (start_year, start_month) = if day >= start_day {
+                    (year, month)
+                } else if month == 1 {
+                    (year - 1, 12)
+                } else {
+                    (year, month - 1)
+                };
+                let (end_year, end_month) = if start_month == 12 {
+                    (start_year + 1, 1)
+                } else {
+                    (start_year, start_month + 1)
+                };
+                (
+                    days_from_civil(start_year, start_month, start_day),
+                    days_from_civil(end_year, end_month, start_day),
+                )
+            }
+        };
+
+        (
+            UNIX_EPOCH + Duration::from_secs(start * SECONDS_PER_DAY),
+            UNIX_EPOCH + Duration::from_secs(end * SECONDS_PER_DAY),
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct QuotaConfig {
+    pub limit_bytes: u64,
+    pub period: QuotaPeriod,
+    pub direction: QuotaDirection,
+    /// Day of the month a monthly period starts on, 1-28
+    pub start_day: u32,
+    /// Usage percentages at which a warning is shown, ascending
+    pub warn_percents: Vec<f64>,
+    pub state_file: PathBuf,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuotaStatus {
+    pub used_bytes: u64,
+    pub limit_bytes: u64,
+    pub period: QuotaPeriod,
+    pub direction: QuotaDirection,
+    pub period_end: SystemTime,
+    /// When the budget runs out at the average rate so far, if that is before the period ends
+    pub projected_exhaustion: Option<SystemTime>,
+    /// Highest warning percentage that usage has reached
+    pub warning: Option<f64>,
+    pub save_error: Option<String>,
+}
+
+impl QuotaStatus {
+    pub fn used_percent(&self) -> f64 {
+        self.used_bytes as f64 / self.limit_bytes as f64 * 100.0
+    }
+
+    pub fn remaining_bytes(&self) -> u64 {
+        self.limit_bytes.saturating_sub(self.used_bytes)
+    }
+}
+
+/// Byte usage for the current quota period, persisted in a small state file so
+/// that it survives restarts.
+pub struct QuotaTracker {
+    config: QuotaConfig,
+    period_start: SystemTime,
+    period_end: SystemTime,
+    inbound_bytes: u64,
+    outbound_bytes: u64,
+    last_save: Option<SystemTime>,
+    save_error: Option<String>,
+}
+
+impl QuotaTracker {
+    /// Restores usage from the state file, unless it belongs to an earlier period.
+    pub fn load(config: QuotaConfig, now: SystemTime) -> Result<Self> {
+        let (period_start, period_end) = config.period.bounds(now, config.start_day);
+        let mut tracker = Self {
+            config,
+            period_start,
+            period_end,
+            inbound_bytes: 0,
+            outbound_bytes: 0,
+            last_save: None,
+            save_error: None,
+        };
+
+        let contents = match fs::read_to_string(&tracker.config.state_file) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(tracker),
+            Err(e) => {
+                return Err(e).with_context(|| {
+                    format!("Failed to read quota state file {}", tracker.config.state_file.display())
+                });
+            }
+        };
+
+        let mut stored_start = None;
+        for line in contents.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            let (key, value) = line.split_once('=').context("Malformed line in quota state file")?;
+            let value: u64 = value
+                .trim()
+                .parse()
+                .with_context(|| format!("Invalid value for '{}' in quota state file", key))?;
+            match key.trim() {
+                "period_start" => stored_start = Some(UNIX_EPOCH + Duration::from_secs(value)),
+                "inbound_bytes" => tracker.inbound_bytes = value,
+                "outbound_bytes" => tracker.outbound_bytes = value,
+                _ => {}
+            }
+        }
+
+        if stored_start != Some(period_start) {
+            tracker.inbound_bytes = 0;
+            tracker.outbound_bytes = 0;
+        }
+
+        Ok(tracker)
+    }
+
+    pub fn add(&mut self, inbound_bytes: u64, outbound_bytes: u64, now: SystemTime) {
+        if now >= self.period_end {
+            (self.period_start, self.period_end) = self.config.period.bounds(now, self.config.start_day);
+            self.inbound_bytes = 0;
+            self.outbound_bytes = 0;
+            self.last_save = None;
+        }
+
+        self.inbound_bytes += inbound_bytes;
+        self.outbound_bytes += outbound_bytes;
+
+        let save_due = self
+            .last_save
+            .is_none_or(|last| now.duration_since(last).unwrap_or_default() >= SAVE_INTERVAL);
+        if save_due {
+            self.last_save = Some(now);
+            // Keep counting if the disk is unhappy; the error is shown in the UI
+            self.save_error = self.save().err().map(|e| format!("{:#}", e));
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let path = &self.config.state_file;
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+
+        let contents = format!(
+            "# tcpgraph quota state\nperiod_start={}\ninbound_bytes={}\noutbound_bytes={}\n",
+            self.period_start.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+            self.inbound_bytes,
+            self.outbound_bytes,
+        );
+
+        // Write then rename, so a crash never leaves a half-written file behind. The suffix is
+        // appended rather than replacing the extension, so quota-eth0.100 and quota-eth0.200
+        // don't share a temp file
+        let mut temp_path = path.clone().into_os_string();
+        temp_path.push(".tmp");
+        let temp_path = PathBuf::from(temp_path);
+        fs::write(&temp_path, contents)
+            .with_context(|| format!("Failed to write {}", temp_path.display()))?;
+        fs::rename(&temp_path, path)
+            .with_context(|| format!("Failed to replace {}", path.display()))?;
+
+        Ok(())
+    }
+
+    pub fn status(&self, now: SystemTime) -> QuotaStatus {
+        let used_bytes = match self.config.direction {
+            QuotaDirection::Inbound => self.inbound_bytes,
+            QuotaDirection::Outbound => self.outbound_bytes,
+            QuotaDirection::Both => self.inbound_bytes + self.outbound_bytes,
+        };
+        let limit_bytes = self.config.limit_bytes.max(1);
+
+        let elapsed = now.duration_since(self.period_start).unwrap_or_default().as_secs_f64();
+        let projected_exhaustion = (used_bytes > 0 && elapsed > 0.0)
+            .then(|| {
+                let rate = used_bytes as f64 / elapsed;
+                let remaining = limit_bytes.saturating_sub(used_bytes) as f64;
+                now + Duration::from_secs_f64(remaining / rate)
+            })
+            .filter(|exhaustion| *exhaustion < self.period_end);
+
+        let used_percent = used_bytes as f64 / limit_bytes as f64 * 100.0;
+        let warning = self
+            .config
+            .warn_percents
+            .iter()
+            .copied()
+            .filter(|percent| used_percent >= *percent)
+            .reduce(f64::max);
+
+        QuotaStatus {
+            used_bytes,
+            limit_bytes,
+            period: self.config.period,
+            direction: self.config.direction,
+            period_end: self.period_end,
+            projected_exhaustion,
+            warning,
+            save_error: self.save_error.clone(),
+        }
+    }
+}
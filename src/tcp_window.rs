@@ -0,0 +1,198 @@
+use crate::capture::FlowKey;
+use pnet::packet::tcp::TcpFlags;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+// Upper bound on tracked flow endpoints so a SYN flood cannot grow the map without limit
+const MAX_TRACKED_ENDPOINTS: usize = 16384;
+
+// Once the table is full, endpoints silent for this long are dropped first
+const ENDPOINT_IDLE_TIMEOUT: Duration = Duration::from_secs(120);
+
+// Room made per eviction, so a full table is scanned once per this many new connections rather than per SYN
+const EVICTION_BATCH: usize = MAX_TRACKED_ENDPOINTS / 8;
+
+#[derive(Debug, Clone)]
+pub struct TcpSegment {
+    pub flow: FlowKey,
+    pub sequence: u32,
+    pub acknowledgement: u32,
+    pub flags: u8,
+    pub window: u16,
+    pub window_scale: Option<u8>,
+    pub payload_len: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowEvent {
+    /// The sender advertised a zero receive window: its application is not reading fast enough
+    ZeroWindow,
+    /// The sender filled the receive window last advertised by its peer
+    WindowFull,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TcpWindowInfo {
+    /// Receive window in bytes; `None` when the handshake was missed, as the window scale is unknown
+    pub advertised_window: Option<u32>,
+    pub event: Option<WindowEvent>,
+}
+
+#[derive(Debug)]
+struct EndpointState {
+    window_scale: Option<u8>,
+    scaling_active: bool,
+    last_ack: Option<u32>,
+    advertised_window: u32,
+    fin_sent: bool,
+    last_seen: SystemTime,
+}
+
+/// Tracks the receive window each TCP endpoint advertises so that zero-window
+/// and window-full conditions can be detected per flow. Only connections whose
+/// handshake was seen are tracked, since the window scale is negotiated there.
+pub struct TcpWindowTracker {
+    endpoints: HashMap<FlowKey, EndpointState>,
+}
+
+impl Default for TcpWindowTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TcpWindowTracker {
+    pub fn new() -> Self {
+        Self {
+            endpoints: HashMap::new(),
+        }
+    }
+
+    pub fn observe(&mut self, segment: &TcpSegment, timestamp: SystemTime) -> TcpWindowInfo {
+        let is_syn = segment.flags & TcpFlags::SYN != 0;
+        let is_ack = segment.flags & TcpFlags::ACK != 0;
+        let is_rst = segment.flags & TcpFlags::RST != 0;
+        let is_fin = segment.flags & TcpFlags::FIN != 0;
+        let reverse_key = segment.flow.reversed();
+
+        // Window scaling is only in effect when both SYN and SYN/ACK carried the option
+        if is_syn
+            && is_ack
+            && segment.window_scale.is_some()
+            && let Some(peer) = self.endpoints.get_mut(&reverse_key)
+            && peer.window_scale.is_some()
+        {
+            peer.scaling_active = true;
+        }
+
+        let peer_scaling_active = self
+            .endpoints
+            .get(&reverse_key)
+            .map(|peer| peer.scaling_active)
+            .unwrap_or(false);
+
+        // Did this segment reach the right edge of the window its receiver advertised?
+        let window_full = segment.payload_len > 0
+            && self.endpoints.get(&reverse_key).is_some_and(|peer| {
+                peer.last_ack.is_some_and(|ack| {
+                    let segment_end = segment.sequence.wrapping_add(segment.payload_len);
+                    let in_flight = segment_end.wrapping_sub(ack) as i32;
+                    peer.advertised_window > 0 && in_flight >= peer.advertised_window as i32
+                })
+            });
+
+        // Without the handshake the window scale is unknown, so only a zero window means
+        // something; an ACK trailing a closed connection lands here too
+        if !is_syn && !self.endpoints.contains_key(&segment.flow) {
+            return TcpWindowInfo {
+                advertised_window: None,
+                event: Self::classify(segment.window as u32, is_syn, is_rst, window_full),
+            };
+        }
+
+        if is_syn && !self.endpoints.contains_key(&segment.flow) && self.endpoints.len() >= MAX_TRACKED_ENDPOINTS {
+            self.evict(timestamp);
+        }
+
+        let state = self.endpoints.entry(segment.flow).or_insert_with(|| EndpointState {
+            window_scale: None,
+            scaling_active: false,
+            last_ack: None,
+            advertised_window: 0,
+            fin_sent: false,
+            last_seen: timestamp,
+        });
+        state.last_seen = timestamp;
+
+        if is_syn {
+            state.window_scale = segment.window_scale;
+            state.scaling_active = is_ack && segment.window_scale.is_some() && peer_scaling_active;
+            state.fin_sent = false;
+        }
+
+        // The window field of SYN segments is never scaled
+        let shift = if !is_syn && state.scaling_active {
+            state.window_scale.unwrap_or(0).min(14)
+        } else {
+            0
+        };
+        let advertised_window = (segment.window as u32) << shift;
+
+        if is_ack {
+            state.last_ack = Some(segment.acknowledgement);
+            state.advertised_window = advertised_window;
+        }
+
+        // After a FIN the endpoint still acknowledges its peer's data, so the
+        // connection is forgotten only once both sides have closed
+        if is_fin {
+            state.fin_sent = true;
+        }
+        let peer_closed = self.endpoints.get(&reverse_key).is_some_and(|peer| peer.fin_sent);
+        if is_rst || (is_fin && peer_closed) {
+            self.endpoints.remove(&segment.flow);
+            self.endpoints.remove(&reverse_key);
+        }
+
+        TcpWindowInfo {
+            advertised_window: Some(advertised_window),
+            event: Self::classify(advertised_window, is_syn, is_rst, window_full),
+        }
+    }
+
+    // Makes room for the next batch of connections: endpoints idle for a while go
+    // first, then those silent the longest
+    fn evict(&mut self, now: SystemTime) {
+        self.endpoints.retain(|_, state| {
+            now.duration_since(state.last_seen).unwrap_or_default() < ENDPOINT_IDLE_TIMEOUT
+        });
+        let excess = (self.endpoints.len() + EVICTION_BATCH).saturating_sub(MAX_TRACKED_ENDPOINTS);
+        if excess == 0 {
+            return;
+        }
+        let mut by_age: Vec<(SystemTime, FlowKey)> = self
+            .endpoints
+            .iter()
+            .map(|(flow, state)| (state.last_seen, *flow))
+            .collect();
+        by_age.select_nth_unstable_by_key(excess - 1, |(last_seen, _)| *last_seen);
+        for (_, flow) in &by_age[..excess] {
+            self.endpoints.remove(flow);
+        }
+    }
+
+    fn classify(
+        advertised_window: u32,
+        is_syn: bool,
+        is_rst: bool,
+        window_full: bool,
+    ) -> Option<WindowEvent> {
+        if advertised_window == 0 && !is_syn && !is_rst {
+            Some(WindowEvent::ZeroWindow)
+        } else if window_full {
+            Some(WindowEvent::WindowFull)
+        } else {
+            None
+        }
+    }
+}
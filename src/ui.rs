@@ -1,58 +1,340 @@
 use anyhow::Result;
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode},
+    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyModifiers},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use ratatui::{
     backend::CrosstermBackend,
-    layout::{Constraint, Direction, Layout},
+    layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     symbols,
     text::{Line, Span},
-    widgets::{Axis, Block, Borders, Chart, Dataset, GraphType, Paragraph},
+    widgets::{Axis, Bar, BarChart, BarGroup, Block, Borders, Chart, Dataset, GraphType, Paragraph},
     Frame, Terminal,
 };
-use crate::bandwidth::DirectionalBandwidth;
+use crate::alert::{AlertState, AlertStatus};
+use crate::anomaly::Anomaly;
+use crate::bandwidth::{BurstPeaks, DirectionalBandwidth, WindowAverage, MonitorCommand, PacketSizeHistogram, RateSummary, TransferTotals, TcpWindowStats, PACKET_SIZE_BUCKETS, PERCENTILE_ERROR_PERCENT};
+use crate::capture::{CaptureDropCounts, MAX_SERIES};
+use crate::counters::KernelComparison;
+use crate::forecast::Forecast;
+use crate::fragment::FragmentStats;
+use crate::history::{HistoryPoint, HistoryTier, RetentionTier, TieredHistory};
+use crate::calendar::format_utc;
+use crate::quota::QuotaStatus;
 use std::collections::VecDeque;
 use std::io;
-use std::sync::mpsc;
-use std::time::Duration;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, mpsc};
+use std::time::{Duration, SystemTime};
+
+// Sub-second intervals would otherwise redraw the terminal hundreds of times a second
+const MIN_REDRAW_INTERVAL: Duration = Duration::from_millis(50);
+// How long a stop request from outside (a signal) can go unnoticed with long intervals
+const STOP_CHECK_INTERVAL: Duration = Duration::from_millis(200);
+
+// One per --series, picked to stay apart from the inbound/outbound green and red
+const SERIES_COLORS: [Color; MAX_SERIES] = [
+    Color::Cyan,
+    Color::Yellow,
+    Color::Magenta,
+    Color::LightBlue,
+    Color::LightGreen,
+    Color::LightRed,
+    Color::LightCyan,
+    Color::LightYellow,
+];
+
+// Points drawn along each forecast line, enough for it to bend where it reaches zero
+const FORECAST_POINTS: usize = 20;
+
+// How many of the latest anomalies are kept for the exit summary
+const RECENT_ANOMALIES: usize = 10;
+
+type Series = Vec<(f64, f64)>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChartMode {
+    Bandwidth,
+    Packets,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BurstResolution {
+    OneMs,
+    TenMs,
+}
+
+impl BurstResolution {
+    fn peak(&self, peaks: &BurstPeaks) -> f64 {
+        match self {
+            BurstResolution::OneMs => peaks.peak_1ms,
+            BurstResolution::TenMs => peaks.peak_10ms,
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            BurstResolution::OneMs => "1ms",
+            BurstResolution::TenMs => "10ms",
+        }
+    }
+}
 
 pub struct App {
     pub inbound_data: VecDeque<(f64, f64)>,
     pub outbound_data: VecDeque<(f64, f64)>,
+    pub inbound_pps_data: VecDeque<(f64, f64)>,
+    pub outbound_pps_data: VecDeque<(f64, f64)>,
+    pub inbound_goodput_data: VecDeque<(f64, f64)>,
+    pub outbound_goodput_data: VecDeque<(f64, f64)>,
+    pub raw_inbound_data: VecDeque<(f64, f64)>,
+    pub raw_outbound_data: VecDeque<(f64, f64)>,
+    pub show_raw: bool,
+    pub inbound_burst_data: VecDeque<(f64, BurstPeaks)>,
+    pub outbound_burst_data: VecDeque<(f64, BurstPeaks)>,
+    pub burst_resolution: Option<BurstResolution>,
+    pub transit_data: VecDeque<(f64, f64)>,
+    pub internal_data: VecDeque<(f64, f64)>,
+    pub broadcast_data: VecDeque<(f64, f64)>,
+    pub current_transit: f64,
+    pub current_internal: f64,
+    pub current_broadcast: f64,
+    pub current_total: f64,
+    pub averages: Vec<WindowAverage>,
+    pub total_label: String,
+    pub series_names: Vec<String>,
+    pub series_data: Vec<VecDeque<(f64, f64)>>,
+    pub current_series: Vec<f64>,
+    pub inbound_summary: RateSummary,
+    pub outbound_summary: RateSummary,
+    pub session_totals: TransferTotals,
+    pub totals_since_reset: TransferTotals,
+    pub history: TieredHistory,
+    pub quota: Option<QuotaStatus>,
+    /// Chart positions (x, Mbps) of anomalies still on screen
+    pub anomaly_markers: VecDeque<(f64, f64)>,
+    pub recent_anomalies: VecDeque<Anomaly>,
+    pub anomaly_count: u64,
+    pub alerts: Option<AlertStatus>,
+    pub alerts_fired: u64,
+    /// How far ahead the trend is drawn, and the interval it is measured in
+    pub forecast_horizon: Option<(Duration, Duration)>,
+    pub inbound_forecast: Option<Forecast>,
+    pub outbound_forecast: Option<Forecast>,
+    /// Link capacity in bytes/s
+    pub link_speed: Option<f64>,
+    /// Scale the bandwidth chart to the link capacity instead of the traffic seen
+    pub capacity_axis: bool,
+    pub kernel: Option<KernelComparison>,
+    pub capture_drops: Option<CaptureDropCounts>,
+    /// 0 is the live chart, `n` shows history tier `n - 1`
+    pub time_range: usize,
     pub current_inbound: f64,
+    /// Rates with Ethernet framing, preamble and gap, for the utilization of the link
+    pub current_inbound_wire: f64,
+    pub current_outbound_wire: f64,
     pub current_outbound: f64,
+    pub current_inbound_pps: f64,
+    pub current_outbound_pps: f64,
+    pub current_inbound_goodput: f64,
+    pub current_outbound_goodput: f64,
+    pub overhead_percent: f64,
+    pub show_goodput: bool,
+    /// False when the rates come from interface counters, which have no goodput or per-packet detail
+    pub packet_details: bool,
     pub max_inbound: f64,
     pub max_outbound: f64,
+    pub max_inbound_pps: f64,
+    pub max_outbound_pps: f64,
+    pub chart_mode: ChartMode,
+    pub size_histogram: PacketSizeHistogram,
+    pub session_size_histogram: PacketSizeHistogram,
+    pub show_session_histogram: bool,
+    pub tcp_windows: TcpWindowStats,
+    pub total_zero_window_events: u64,
+    pub total_window_full_events: u64,
+    pub active_flows: usize,
+    pub fragments: FragmentStats,
+    pub total_fragments: FragmentStats,
     pub interface: String,
     pub filter: String,
     pub should_quit: bool,
     pub tick_count: usize,
+    pub visible_points: usize,
 }
 
 impl App {
-    pub fn new(interface: String, filter: String) -> Self {
+    pub fn new(
+        interface: String,
+        filter: String,
+        visible_points: usize,
+        total_label: String,
+        series_names: Vec<String>,
+        retention: &[RetentionTier],
+    ) -> Self {
         Self {
             inbound_data: VecDeque::new(),
             outbound_data: VecDeque::new(),
+            inbound_pps_data: VecDeque::new(),
+            outbound_pps_data: VecDeque::new(),
+            inbound_goodput_data: VecDeque::new(),
+            outbound_goodput_data: VecDeque::new(),
+            raw_inbound_data: VecDeque::new(),
+            raw_outbound_data: VecDeque::new(),
+            show_raw: false,
+            inbound_burst_data: VecDeque::new(),
+            outbound_burst_data: VecDeque::new(),
+            burst_resolution: Some(BurstResolution::TenMs),
+            transit_data: VecDeque::new(),
+            internal_data: VecDeque::new(),
+            broadcast_data: VecDeque::new(),
+            current_transit: 0.0,
+            current_internal: 0.0,
+            current_broadcast: 0.0,
+            current_total: 0.0,
+            averages: Vec::new(),
+            total_label,
+            series_data: vec![VecDeque::new(); series_names.len()],
+            current_series: vec![0.0; series_names.len()],
+            series_names,
+            inbound_summary: RateSummary::default(),
+            outbound_summary: RateSummary::default(),
+            session_totals: TransferTotals::new(SystemTime::now()),
+            totals_since_reset: TransferTotals::new(SystemTime::now()),
+            history: TieredHistory::new(retention),
+            quota: None,
+            anomaly_markers: VecDeque::new(),
+            recent_anomalies: VecDeque::new(),
+            anomaly_count: 0,
+            alerts: None,
+            alerts_fired: 0,
+            forecast_horizon: None,
+            inbound_forecast: None,
+            outbound_forecast: None,
+            link_speed: None,
+            capacity_axis: false,
+            kernel: None,
+            capture_drops: None,
+            time_range: 0,
             current_inbound: 0.0,
+            current_inbound_wire: 0.0,
+            current_outbound_wire: 0.0,
             current_outbound: 0.0,
+            current_inbound_pps: 0.0,
+            current_outbound_pps: 0.0,
+            current_inbound_goodput: 0.0,
+            current_outbound_goodput: 0.0,
+            overhead_percent: 0.0,
+            show_goodput: true,
+            packet_details: true,
             max_inbound: 0.0,
             max_outbound: 0.0,
+            max_inbound_pps: 0.0,
+            max_outbound_pps: 0.0,
+            chart_mode: ChartMode::Bandwidth,
+            size_histogram: PacketSizeHistogram::default(),
+            session_size_histogram: PacketSizeHistogram::default(),
+            show_session_histogram: false,
+            tcp_windows: TcpWindowStats::default(),
+            total_zero_window_events: 0,
+            total_window_full_events: 0,
+            active_flows: 0,
+            fragments: FragmentStats::default(),
+            total_fragments: FragmentStats::default(),
             interface,
             filter,
             should_quit: false,
             tick_count: 0,
+            visible_points,
         }
     }
 
+    /// Draws the bandwidth trend `horizon` into the future of the live chart.
+    pub fn with_forecast(mut self, horizon: Duration, update_interval: Duration) -> Self {
+        self.forecast_horizon = Some((horizon, update_interval));
+        self
+    }
+
+    /// Hides the goodput and burst overlays, which need captured packets.
+    pub fn without_packet_details(mut self) -> Self {
+        self.packet_details = false;
+        self.show_goodput = false;
+        self.burst_resolution = None;
+        self
+    }
+
+    pub fn with_link_speed(mut self, link_speed: Option<f64>) -> Self {
+        self.link_speed = link_speed;
+        self
+    }
+
+    fn forecast_steps(&self) -> usize {
+        self.forecast_horizon.map_or(0, |(horizon, update_interval)| {
+            ((horizon.as_secs_f64() / update_interval.as_secs_f64()).round() as usize).max(1)
+        })
+    }
+
+    /// Direction and time until the forecast reaches the link speed, if that is within the horizon.
+    pub fn saturation(&self) -> Option<(&'static str, Duration)> {
+        let (horizon, update_interval) = self.forecast_horizon?;
+        let link_speed = self.link_speed?;
+        [("in", self.inbound_forecast), ("out", self.outbound_forecast)]
+            .into_iter()
+            .filter_map(|(direction, forecast)| Some((direction, forecast?.time_to_reach(link_speed, update_interval)?)))
+            .filter(|(_, eta)| *eta <= horizon)
+            .min_by_key(|(_, eta)| *eta)
+    }
+
     pub fn update(&mut self, bandwidth: DirectionalBandwidth) {
         self.current_inbound = bandwidth.inbound;
+        self.current_inbound_wire = bandwidth.inbound_wire;
+        self.current_outbound_wire = bandwidth.outbound_wire;
         self.current_outbound = bandwidth.outbound;
         self.max_inbound = self.max_inbound.max(bandwidth.inbound);
         self.max_outbound = self.max_outbound.max(bandwidth.outbound);
+        self.current_inbound_pps = bandwidth.inbound_pps;
+        self.current_outbound_pps = bandwidth.outbound_pps;
+        self.max_inbound_pps = self.max_inbound_pps.max(bandwidth.inbound_pps);
+        self.max_outbound_pps = self.max_outbound_pps.max(bandwidth.outbound_pps);
+        self.current_transit = bandwidth.transit;
+        self.current_internal = bandwidth.internal;
+        self.current_broadcast = bandwidth.broadcast;
+        self.current_total = bandwidth.total;
+        self.averages = bandwidth.averages.clone();
+        self.inbound_forecast = bandwidth.inbound_forecast;
+        self.outbound_forecast = bandwidth.outbound_forecast;
+        for (current, rate) in self.current_series.iter_mut().zip(&bandwidth.series) {
+            *current = *rate;
+        }
+        self.inbound_summary = bandwidth.inbound_summary;
+        self.outbound_summary = bandwidth.outbound_summary;
+        self.session_totals = bandwidth.session_totals;
+        self.totals_since_reset = bandwidth.totals_since_reset;
+        self.quota = bandwidth.quota.clone();
+        if let Some(alerts) = &bandwidth.alerts {
+            self.alerts_fired += alerts.events.iter().filter(|event| event.state == AlertState::Firing).count() as u64;
+        }
+        self.alerts = bandwidth.alerts.clone();
+        if bandwidth.kernel.is_some() {
+            self.kernel = bandwidth.kernel;
+        }
+        self.capture_drops = bandwidth.capture_drops;
+        self.history.record(bandwidth.timestamp, bandwidth.raw_inbound, bandwidth.raw_outbound);
+        self.current_inbound_goodput = bandwidth.inbound_goodput;
+        self.current_outbound_goodput = bandwidth.outbound_goodput;
+        self.overhead_percent = bandwidth.overhead_percent();
+        self.total_zero_window_events += bandwidth.tcp_windows.zero_window_events;
+        self.total_window_full_events += bandwidth.tcp_windows.window_full_events;
+        self.tcp_windows = bandwidth.tcp_windows;
+        self.active_flows = bandwidth.active_flows;
+        self.total_fragments.fragmented_datagrams += bandwidth.fragments.fragmented_datagrams;
+        self.total_fragments.reassembly_timeouts += bandwidth.fragments.reassembly_timeouts;
+        self.total_fragments.overflow_drops += bandwidth.fragments.overflow_drops;
+        self.fragments = bandwidth.fragments;
+        self.size_histogram = bandwidth.size_histogram;
+        self.session_size_histogram = bandwidth.session_size_histogram;
         
         let x = self.tick_count as f64;
         // Convert bytes/s to Mbps: bytes/s * 8 bits/byte / 1,000,000 bits/Mbps
@@ -61,17 +343,117 @@ impl App {
         
         self.inbound_data.push_back((x, inbound_mbps));
         self.outbound_data.push_back((x, outbound_mbps));
+        self.inbound_pps_data.push_back((x, bandwidth.inbound_pps));
+        self.outbound_pps_data.push_back((x, bandwidth.outbound_pps));
+        self.inbound_goodput_data.push_back((x, bandwidth.inbound_goodput * 8.0 / 1_000_000.0));
+        self.outbound_goodput_data.push_back((x, bandwidth.outbound_goodput * 8.0 / 1_000_000.0));
+        self.raw_inbound_data.push_back((x, bandwidth.raw_inbound * 8.0 / 1_000_000.0));
+        self.raw_outbound_data.push_back((x, bandwidth.raw_outbound * 8.0 / 1_000_000.0));
+        self.inbound_burst_data.push_back((x, bandwidth.inbound_burst));
+        self.transit_data.push_back((x, bandwidth.transit * 8.0 / 1_000_000.0));
+        self.internal_data.push_back((x, bandwidth.internal * 8.0 / 1_000_000.0));
+        self.broadcast_data.push_back((x, bandwidth.broadcast * 8.0 / 1_000_000.0));
+        self.outbound_burst_data.push_back((x, bandwidth.outbound_burst));
+        for (data, rate) in self.series_data.iter_mut().zip(&bandwidth.series) {
+            data.push_back((x, rate * 8.0 / 1_000_000.0));
+            if data.len() > self.visible_points {
+                data.pop_front();
+            }
+        }
+        for anomaly in &bandwidth.anomalies {
+            self.anomaly_markers.push_back((x, anomaly.rate * 8.0 / 1_000_000.0));
+            self.recent_anomalies.push_back(*anomaly);
+            self.anomaly_count += 1;
+        }
         
-        if self.inbound_data.len() > 100 {
+        if self.inbound_data.len() > self.visible_points {
             self.inbound_data.pop_front();
         }
-        if self.outbound_data.len() > 100 {
+        if self.outbound_data.len() > self.visible_points {
             self.outbound_data.pop_front();
         }
+        if self.inbound_pps_data.len() > self.visible_points {
+            self.inbound_pps_data.pop_front();
+        }
+        if self.outbound_pps_data.len() > self.visible_points {
+            self.outbound_pps_data.pop_front();
+        }
+        if self.inbound_goodput_data.len() > self.visible_points {
+            self.inbound_goodput_data.pop_front();
+        }
+        if self.outbound_goodput_data.len() > self.visible_points {
+            self.outbound_goodput_data.pop_front();
+        }
+        if self.raw_inbound_data.len() > self.visible_points {
+            self.raw_inbound_data.pop_front();
+        }
+        if self.raw_outbound_data.len() > self.visible_points {
+            self.raw_outbound_data.pop_front();
+        }
+        if self.inbound_burst_data.len() > self.visible_points {
+            self.inbound_burst_data.pop_front();
+        }
+        if self.outbound_burst_data.len() > self.visible_points {
+            self.outbound_burst_data.pop_front();
+        }
+        if self.transit_data.len() > self.visible_points {
+            self.transit_data.pop_front();
+        }
+        if self.internal_data.len() > self.visible_points {
+            self.internal_data.pop_front();
+        }
+        if self.broadcast_data.len() > self.visible_points {
+            self.broadcast_data.pop_front();
+        }
+        while self.anomaly_markers.front().is_some_and(|(marker_x, _)| *marker_x + self.visible_points as f64 <= x) {
+            self.anomaly_markers.pop_front();
+        }
+        while self.recent_anomalies.len() > RECENT_ANOMALIES {
+            self.recent_anomalies.pop_front();
+        }
         
         self.tick_count += 1;
     }
 
+    pub fn toggle_chart_mode(&mut self) {
+        self.chart_mode = match self.chart_mode {
+            ChartMode::Bandwidth => ChartMode::Packets,
+            ChartMode::Packets => ChartMode::Bandwidth,
+        };
+    }
+
+    pub fn cycle_time_range(&mut self) {
+        self.time_range = (self.time_range + 1) % (self.history.tiers().len() + 1);
+    }
+
+    pub fn selected_tier(&self) -> Option<&HistoryTier> {
+        self.time_range.checked_sub(1).and_then(|tier| self.history.tiers().get(tier))
+    }
+
+    pub fn toggle_goodput(&mut self) {
+        self.show_goodput = !self.show_goodput;
+    }
+
+    pub fn toggle_raw(&mut self) {
+        self.show_raw = !self.show_raw;
+    }
+
+    pub fn cycle_burst_resolution(&mut self) {
+        self.burst_resolution = match self.burst_resolution {
+            Some(BurstResolution::TenMs) => Some(BurstResolution::OneMs),
+            Some(BurstResolution::OneMs) => None,
+            None => Some(BurstResolution::TenMs),
+        };
+    }
+
+    pub fn toggle_capacity_axis(&mut self) {
+        self.capacity_axis = !self.capacity_axis && self.link_speed.is_some();
+    }
+
+    pub fn toggle_histogram_scope(&mut self) {
+        self.show_session_histogram = !self.show_session_histogram;
+    }
+
     pub fn quit(&mut self) {
         self.should_quit = true;
     }
@@ -80,8 +462,10 @@ impl App {
 pub fn run_ui(
     mut app: App,
     bandwidth_rx: mpsc::Receiver<DirectionalBandwidth>,
+    command_tx: mpsc::Sender<MonitorCommand>,
     update_interval: Duration,
-) -> Result<()> {
+    stop: Arc<AtomicBool>,
+) -> Result<App> {
     enable_raw_mode()?;
     let mut stdout = io::stdout();
     execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
@@ -89,34 +473,64 @@ pub fn run_ui(
     let mut terminal = Terminal::new(backend)?;
 
     let mut last_tick = std::time::Instant::now();
-    let tick_rate = update_interval;
+    let tick_rate = update_interval.max(MIN_REDRAW_INTERVAL);
 
     loop {
         terminal.draw(|f| ui(f, &app))?;
 
         let timeout = tick_rate
             .checked_sub(last_tick.elapsed())
-            .unwrap_or_else(|| Duration::from_secs(0));
+            .unwrap_or_else(|| Duration::from_secs(0))
+            .min(STOP_CHECK_INTERVAL);
 
         if crossterm::event::poll(timeout)? {
             if let Event::Key(key) = event::read()? {
                 match key.code {
+                    // Raw mode turns Ctrl+C into a key press instead of SIGINT
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                        app.quit();
+                    }
                     KeyCode::Char('q') | KeyCode::Esc => {
                         app.quit();
                     }
+                    KeyCode::Char('p') => {
+                        app.toggle_chart_mode();
+                    }
+                    KeyCode::Char('g') => {
+                        app.toggle_goodput();
+                    }
+                    KeyCode::Char('r') => {
+                        app.toggle_raw();
+                    }
+                    KeyCode::Char('b') => {
+                        app.cycle_burst_resolution();
+                    }
+                    KeyCode::Char('t') => {
+                        app.cycle_time_range();
+                    }
+                    KeyCode::Char('c') => {
+                        // The monitor may already be gone while shutting down
+                        let _ = command_tx.send(MonitorCommand::ResetTotals(SystemTime::now()));
+                    }
+                    KeyCode::Char('h') => {
+                        app.toggle_histogram_scope();
+                    }
+                    KeyCode::Char('l') => {
+                        app.toggle_capacity_axis();
+                    }
                     _ => {}
                 }
             }
         }
 
         if last_tick.elapsed() >= tick_rate {
-            if let Ok(bandwidth) = bandwidth_rx.try_recv() {
+            while let Ok(bandwidth) = bandwidth_rx.try_recv() {
                 app.update(bandwidth);
             }
             last_tick = std::time::Instant::now();
         }
 
-        if app.should_quit {
+        if app.should_quit || stop.load(Ordering::Relaxed) {
             break;
         }
     }
@@ -129,7 +543,7 @@ pub fn run_ui(
     )?;
     terminal.show_cursor()?;
 
-    Ok(())
+    Ok(app)
 }
 
 fn ui(f: &mut Frame, app: &App) {
@@ -139,7 +553,12 @@ fn ui(f: &mut Frame, app: &App) {
         .constraints([
             Constraint::Length(3),
             Constraint::Min(0),
-            Constraint::Length(3),
+            Constraint::Length(
+                9 + app.quota.is_some() as u16
+                    + app.alerts.is_some() as u16
+                    + (app.kernel.is_some() || app.capture_drops.is_some()) as u16
+                    + !app.series_names.is_empty() as u16,
+            ),
         ])
         .split(f.size());
 
@@ -156,31 +575,225 @@ fn ui(f: &mut Frame, app: &App) {
     
     f.render_widget(title, chunks[0]);
 
-    let inbound_data: Vec<(f64, f64)> = app.inbound_data.iter().cloned().collect();
-    let outbound_data: Vec<(f64, f64)> = app.outbound_data.iter().cloned().collect();
+    let graph_chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Min(0), Constraint::Length(34)])
+        .split(chunks[1]);
+
+    let (inbound_source, outbound_source, unit, chart_title) = match app.chart_mode {
+        ChartMode::Bandwidth => (&app.inbound_data, &app.outbound_data, "Mbps", "Bandwidth Over Time"),
+        ChartMode::Packets => (&app.inbound_pps_data, &app.outbound_pps_data, "pps", "Packet Rate Over Time"),
+    };
+    let inbound_data: Vec<(f64, f64)> = inbound_source.iter().cloned().collect();
+    let outbound_data: Vec<(f64, f64)> = outbound_source.iter().cloned().collect();
     
-    let datasets = vec![
+    let inbound_goodput_data: Vec<(f64, f64)> = app.inbound_goodput_data.iter().cloned().collect();
+    let outbound_goodput_data: Vec<(f64, f64)> = app.outbound_goodput_data.iter().cloned().collect();
+    let raw_inbound_data: Vec<(f64, f64)> = app.raw_inbound_data.iter().cloned().collect();
+    let raw_outbound_data: Vec<(f64, f64)> = app.raw_outbound_data.iter().cloned().collect();
+    let burst_series = |source: &VecDeque<(f64, BurstPeaks)>, resolution: BurstResolution| -> Vec<(f64, f64)> {
+        source
+            .iter()
+            .map(|(x, peaks)| (*x, resolution.peak(peaks) * 8.0 / 1_000_000.0))
+            .collect()
+    };
+    let burst_resolution = app.burst_resolution.filter(|_| app.chart_mode == ChartMode::Bandwidth);
+    let (inbound_burst_data, outbound_burst_data) = match burst_resolution {
+        Some(resolution) => (
+            burst_series(&app.inbound_burst_data, resolution),
+            burst_series(&app.outbound_burst_data, resolution),
+        ),
+        None => (Vec::new(), Vec::new()),
+    };
+    
+    let mut datasets = vec![
         Dataset::default()
-            .name("Inbound (Mbps)")
+            .name(format!("Inbound ({})", unit))
             .marker(symbols::Marker::Braille)
             .style(Style::default().fg(Color::Green))
             .graph_type(GraphType::Line)
             .data(&inbound_data),
         Dataset::default()
-            .name("Outbound (Mbps)")
+            .name(format!("Outbound ({})", unit))
             .marker(symbols::Marker::Braille)
             .style(Style::default().fg(Color::Red))
             .graph_type(GraphType::Line)
             .data(&outbound_data),
     ];
 
-    let x_max = if app.tick_count > 100 {
-        app.tick_count as f64
+    // Goodput is drawn as dots so it reads as a dashed line under the throughput
+    if app.show_goodput && app.chart_mode == ChartMode::Bandwidth {
+        datasets.push(
+            Dataset::default()
+                .name("Goodput In")
+                .marker(symbols::Marker::Dot)
+                .style(Style::default().fg(Color::LightGreen))
+                .graph_type(GraphType::Scatter)
+                .data(&inbound_goodput_data),
+        );
+        datasets.push(
+            Dataset::default()
+                .name("Goodput Out")
+                .marker(symbols::Marker::Dot)
+                .style(Style::default().fg(Color::LightRed))
+                .graph_type(GraphType::Scatter)
+                .data(&outbound_goodput_data),
+        );
+    }
+
+    // Unsmoothed per-interval rates, to compare against the smoothed lines
+    if app.show_raw && app.chart_mode == ChartMode::Bandwidth {
+        datasets.push(
+            Dataset::default()
+                .name("Raw In")
+                .marker(symbols::Marker::Braille)
+                .style(Style::default().fg(Color::Cyan))
+                .graph_type(GraphType::Line)
+                .data(&raw_inbound_data),
+        );
+        datasets.push(
+            Dataset::default()
+                .name("Raw Out")
+                .marker(symbols::Marker::Braille)
+                .style(Style::default().fg(Color::Magenta))
+                .graph_type(GraphType::Line)
+                .data(&raw_outbound_data),
+        );
+    }
+
+    // Peak rate inside each interval, drawn as a dotted line above the average
+    if let Some(resolution) = burst_resolution {
+        datasets.push(
+            Dataset::default()
+                .name(format!("Burst In ({})", resolution.label()))
+                .marker(symbols::Marker::Dot)
+                .style(Style::default().fg(Color::Yellow))
+                .graph_type(GraphType::Line)
+                .data(&inbound_burst_data),
+        );
+        datasets.push(
+            Dataset::default()
+                .name(format!("Burst Out ({})", resolution.label()))
+                .marker(symbols::Marker::Dot)
+                .style(Style::default().fg(Color::LightBlue))
+                .graph_type(GraphType::Line)
+                .data(&outbound_burst_data),
+        );
+    }
+
+    // Intervals that stood out from the baseline, at their unsmoothed rate
+    let anomaly_data: Vec<(f64, f64)> = if app.chart_mode == ChartMode::Bandwidth {
+        app.anomaly_markers.iter().cloned().collect()
     } else {
-        100.0
+        Vec::new()
+    };
+    if !anomaly_data.is_empty() {
+        datasets.push(
+            Dataset::default()
+                .name("Anomaly")
+                .marker(symbols::Marker::Block)
+                .style(Style::default().fg(Color::White))
+                .graph_type(GraphType::Scatter)
+                .data(&anomaly_data),
+        );
+    }
+
+    // Other directions get a line only once they have carried traffic on screen,
+    // so a plain host's legend stays at inbound/outbound
+    let category_data: Vec<(&str, Color, Series)> = [
+        ("Transit", Color::Blue, &app.transit_data),
+        ("Internal", Color::Gray, &app.internal_data),
+        ("Broadcast", Color::LightMagenta, &app.broadcast_data),
+    ]
+    .into_iter()
+    .filter(|(_, _, source)| app.chart_mode == ChartMode::Bandwidth && source.iter().any(|(_, mbps)| *mbps > 0.0))
+    .map(|(name, color, source)| (name, color, source.iter().cloned().collect()))
+    .collect();
+    for (name, color, data) in &category_data {
+        datasets.push(
+            Dataset::default()
+                .name(format!("{} ({})", name, unit))
+                .marker(symbols::Marker::Braille)
+                .style(Style::default().fg(*color))
+                .graph_type(GraphType::Line)
+                .data(data),
+        );
+    }
+
+    // Each --series gets its own line, drawn over everything else
+    let series_data: Vec<(&str, Color, Series)> = if app.chart_mode == ChartMode::Bandwidth {
+        app.series_names
+            .iter()
+            .zip(&app.series_data)
+            .zip(SERIES_COLORS)
+            .map(|((name, source), color)| (name.as_str(), color, source.iter().cloned().collect()))
+            .collect()
+    } else {
+        Vec::new()
+    };
+    for (name, color, data) in &series_data {
+        datasets.push(
+            Dataset::default()
+                .name(format!("{} ({})", name, unit))
+                .marker(symbols::Marker::Braille)
+                .style(Style::default().fg(*color))
+                .graph_type(GraphType::Line)
+                .data(data),
+        );
+    }
+
+    // The projected trend continues from the newest point into the future part of the chart,
+    // which takes at most half the width however long the horizon is
+    let forecast_steps = if app.chart_mode == ChartMode::Bandwidth {
+        app.forecast_steps().min((app.visible_points / 2).max(1))
+    } else {
+        0
+    };
+    let forecast_series = |forecast: Option<Forecast>, last_point: Option<&(f64, f64)>| -> Series {
+        let (Some(forecast), Some((last_x, last_mbps))) = (forecast.filter(|_| forecast_steps > 0), last_point) else {
+            return Vec::new();
+        };
+        (0..=FORECAST_POINTS)
+            .map(|point| {
+                let steps = forecast_steps as f64 * point as f64 / FORECAST_POINTS as f64;
+                // Starts where the plotted line ends rather than at the fitted level, so there is no jump
+                let mbps = forecast.project(last_mbps * 1_000_000.0 / 8.0, steps) * 8.0 / 1_000_000.0;
+                // Past the link speed the projection means nothing
+                let mbps = app.link_speed.map_or(mbps, |speed| mbps.min(speed * 8.0 / 1_000_000.0));
+                (last_x + steps, mbps)
+            })
+            .collect()
     };
-    let x_min = if app.tick_count > 100 {
-        (app.tick_count - 100) as f64
+    let inbound_forecast_data = forecast_series(app.inbound_forecast, app.inbound_data.back());
+    let outbound_forecast_data = forecast_series(app.outbound_forecast, app.outbound_data.back());
+    if !inbound_forecast_data.is_empty() {
+        datasets.push(
+            Dataset::default()
+                .name("Forecast In")
+                .marker(symbols::Marker::Dot)
+                .style(Style::default().fg(Color::Green))
+                .graph_type(GraphType::Line)
+                .data(&inbound_forecast_data),
+        );
+    }
+    if !outbound_forecast_data.is_empty() {
+        datasets.push(
+            Dataset::default()
+                .name("Forecast Out")
+                .marker(symbols::Marker::Dot)
+                .style(Style::default().fg(Color::Red))
+                .graph_type(GraphType::Line)
+                .data(&outbound_forecast_data),
+        );
+    }
+
+    let x_max = if app.tick_count > app.visible_points {
+        app.tick_count as f64
+    } else {
+        app.visible_points as f64
+    } + forecast_steps as f64;
+    let x_min = if app.tick_count > app.visible_points {
+        (app.tick_count - app.visible_points) as f64
     } else {
         0.0
     };
@@ -190,8 +803,711 @@ fn ui(f: &mut Frame, app: &App) {
     let current_outbound_mbps = app.current_outbound * 8.0 / 1_000_000.0;
     let max_inbound_mbps = app.max_inbound * 8.0 / 1_000_000.0;
     let max_outbound_mbps = app.max_outbound * 8.0 / 1_000_000.0;
-    let max_mbps = max_inbound_mbps.max(max_outbound_mbps);
+    // Burst peaks, anomalies, series and the other directions only stretch the axis while they are on screen
+    let max_burst_mbps = inbound_burst_data
+        .iter()
+        .chain(outbound_burst_data.iter())
+        .chain(anomaly_data.iter())
+        .chain(category_data.iter().chain(&series_data).flat_map(|(_, _, data)| data.iter()))
+        .chain(inbound_forecast_data.iter().chain(&outbound_forecast_data))
+        .map(|(_, mbps)| *mbps)
+        .fold(0.0, f64::max);
+    let max_mbps = max_inbound_mbps.max(max_outbound_mbps).max(max_burst_mbps);
+    
+    let link_mbps = app
+        .link_speed
+        .filter(|_| app.capacity_axis && app.chart_mode == ChartMode::Bandwidth)
+        .map(|speed| speed * 8.0 / 1_000_000.0);
+    let capacity_data: Series = link_mbps.map_or_else(Vec::new, |mbps| vec![(x_min, mbps), (x_max, mbps)]);
+    if let Some(mbps) = link_mbps {
+        datasets.push(
+            Dataset::default()
+                .name(format!("Link ({:.0} Mbps)", mbps))
+                .marker(symbols::Marker::Braille)
+                .style(Style::default().fg(Color::DarkGray))
+                .graph_type(GraphType::Line)
+                .data(&capacity_data),
+        );
+    }
+
+    let (y_max, y_labels) = match (app.chart_mode, link_mbps) {
+        (ChartMode::Bandwidth, Some(mbps)) => capacity_axis(mbps),
+        (ChartMode::Bandwidth, None) => bandwidth_axis(max_mbps),
+        (ChartMode::Packets, _) => packet_rate_axis(app.max_inbound_pps.max(app.max_outbound_pps)),
+    };
+
+    let chart = Chart::new(datasets)
+        .block(
+            Block::default()
+                .title(chart_title)
+                .borders(Borders::ALL),
+        )
+        .x_axis(
+            Axis::default()
+                .title("Time")
+                .style(Style::default().fg(Color::Gray))
+                .bounds([x_min, x_max])
+                .labels(vec![
+                    Span::styled(format!("{:.0}", x_min), Style::default().add_modifier(Modifier::BOLD)),
+                    Span::styled(format!("{:.0}", (x_min + x_max) / 2.0), Style::default().add_modifier(Modifier::BOLD)),
+                    Span::styled(format!("{:.0}", x_max), Style::default().add_modifier(Modifier::BOLD)),
+                ]),
+        )
+        .y_axis(
+            Axis::default()
+                .title(unit)
+                .style(Style::default().fg(Color::Gray))
+                .bounds([0.0, y_max])
+                .labels(y_labels),
+        );
+
+    match app.selected_tier() {
+        Some(tier) => render_history_chart(f, graph_chunks[0], tier),
+        None => f.render_widget(chart, graph_chunks[0]),
+    }
+
+    let (histogram, scope) = if app.show_session_histogram {
+        (&app.session_size_histogram, "session")
+    } else {
+        (&app.size_histogram, "interval")
+    };
+    let bars: Vec<Bar> = (0..PACKET_SIZE_BUCKETS.len())
+        .map(|index| {
+            Bar::default()
+                .value(histogram.counts[index])
+                .label(Line::from(PacketSizeHistogram::bucket_label(index)))
+                .text_value(format_count(histogram.counts[index] as f64))
+        })
+        .collect();
+    let size_chart = BarChart::default()
+        .block(
+            Block::default()
+                .title(format!("Packet Sizes ({}, {})", scope, format_count(histogram.total() as f64)))
+                .borders(Borders::ALL),
+        )
+        .direction(Direction::Horizontal)
+        .bar_width(1)
+        .bar_gap(0)
+        .bar_style(Style::default().fg(Color::Cyan))
+        .value_style(Style::default().fg(Color::White).add_modifier(Modifier::BOLD))
+        .data(BarGroup::default().bars(&bars));
+
+    f.render_widget(size_chart, graph_chunks[1]);
+
+    let average_labels: Vec<String> = app.averages.iter().map(|average| format_span(average.window)).collect();
+    let format_averages = |rate: fn(&WindowAverage) -> f64| -> String {
+        app.averages
+            .iter()
+            .map(|average| format!("{:.2}", rate(average) * 8.0 / 1_000_000.0))
+            .collect::<Vec<_>>()
+            .join("/")
+    };
+    let mut statistics = vec![
+        Line::from(vec![
+            Span::raw("↓ In: "),
+            Span::styled(
+                format!("{:.2} Mbps", current_inbound_mbps),
+                Style::default().fg(Color::Green).add_modifier(Modifier::BOLD),
+            ),
+            utilization_span(app.current_inbound_wire, app.link_speed),
+            Span::raw(" | ↑ Out: "),
+            Span::styled(
+                format!("{:.2} Mbps", current_outbound_mbps),
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            ),
+            utilization_span(app.current_outbound_wire, app.link_speed),
+            Span::raw(" | Goodput: ↓"),
+            Span::styled(
+                packet_detail(app, format!("{:.2}", app.current_inbound_goodput * 8.0 / 1_000_000.0)),
+                Style::default().fg(Color::LightGreen).add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(" ↑"),
+            Span::styled(
+                packet_detail(app, format!("{:.2}", app.current_outbound_goodput * 8.0 / 1_000_000.0)),
+                Style::default().fg(Color::LightRed).add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(" | Overhead: "),
+            Span::styled(
+                packet_detail(app, format!("{:.1}%", app.overhead_percent)),
+                Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(" | pps: ↓"),
+            Span::styled(
+                format_count(app.current_inbound_pps),
+                Style::default().fg(Color::Green).add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(" ↑"),
+            Span::styled(
+                format_count(app.current_outbound_pps),
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(" | Max: ↓"),
+            Span::styled(
+                format!("{:.1}", max_inbound_mbps),
+                Style::default().fg(Color::Green).add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(" ↑"),
+            Span::styled(
+                format!("{:.1}", max_outbound_mbps),
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            ),
+        ]),
+        Line::from(vec![
+            Span::raw("TCP zero-window: "),
+            Span::styled(
+                format!("{}", app.tcp_windows.zero_window_events),
+                Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(format!(" (total {})", app.total_zero_window_events)),
+            Span::raw(" | Window-full: "),
+            Span::styled(
+                format!("{}", app.tcp_windows.window_full_events),
+                Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(format!(" (total {})", app.total_window_full_events)),
+            Span::raw(" | Min window: "),
+            Span::styled(
+                app.tcp_windows
+                    .min_advertised_window
+                    .map_or_else(|| "-".to_string(), format_window),
+                Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(" | Burst 1ms: ↓"),
+            Span::styled(
+                format!("{:.1}", latest_burst(&app.inbound_burst_data).peak_1ms * 8.0 / 1_000_000.0),
+                Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(" ↑"),
+            Span::styled(
+                format!("{:.1}", latest_burst(&app.outbound_burst_data).peak_1ms * 8.0 / 1_000_000.0),
+                Style::default().fg(Color::LightBlue).add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(" | 10ms: ↓"),
+            Span::styled(
+                format!("{:.1}", latest_burst(&app.inbound_burst_data).peak_10ms * 8.0 / 1_000_000.0),
+                Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(" ↑"),
+            Span::styled(
+                format!("{:.1}", latest_burst(&app.outbound_burst_data).peak_10ms * 8.0 / 1_000_000.0),
+                Style::default().fg(Color::LightBlue).add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(" Mbps"),
+        ]),
+        Line::from(vec![
+            Span::raw(format!("Averages {}: ↓", average_labels.join("/"))),
+            Span::styled(
+                format_averages(|average| average.inbound),
+                Style::default().fg(Color::Green).add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(" ↑"),
+            Span::styled(
+                format_averages(|average| average.outbound),
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(" Mbps"),
+            forecast_span(app),
+        ]),
+        Line::from(vec![
+            Span::raw("Transit: "),
+            Span::styled(
+                format!("{:.2} Mbps", app.current_transit * 8.0 / 1_000_000.0),
+                Style::default().fg(Color::Blue).add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(" | Internal: "),
+            Span::styled(
+                format!("{:.2} Mbps", app.current_internal * 8.0 / 1_000_000.0),
+                Style::default().fg(Color::Gray).add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(" | Broadcast/multicast: "),
+            Span::styled(
+                format!("{:.2} Mbps", app.current_broadcast * 8.0 / 1_000_000.0),
+                Style::default().fg(Color::LightMagenta).add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(format!(" | Total ({}): ", app.total_label)),
+            Span::styled(
+                format!("{:.2} Mbps", app.current_total * 8.0 / 1_000_000.0),
+                Style::default().fg(Color::White).add_modifier(Modifier::BOLD),
+            ),
+        ]),
+        Line::from(vec![
+            Span::raw("Session mean/p50/p90/p95/p99: ↓"),
+            Span::styled(
+                format_summary(&app.inbound_summary),
+                Style::default().fg(Color::Green).add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(" ↑"),
+            Span::styled(
+                format_summary(&app.outbound_summary),
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(format!(
+                " Mbps ({} intervals, ±{}%)",
+                app.inbound_summary.samples, PERCENTILE_ERROR_PERCENT
+            )),
+            Span::raw(" | Anomalies: "),
+            Span::styled(
+                format!("{}", app.anomaly_count),
+                Style::default().fg(Color::White).add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(
+                app.recent_anomalies
+                    .back()
+                    .map(|anomaly| {
+                        format!(
+                            " (last: {} {:.2} Mbps, {:+.1}σ, {} ago)",
+                            anomaly.direction.name(),
+                            anomaly.rate * 8.0 / 1_000_000.0,
+                            anomaly.sigmas,
+                            format_elapsed(anomaly.timestamp)
+                        )
+                    })
+                    .unwrap_or_default(),
+            ),
+        ]),
+        Line::from(vec![
+            Span::raw("Transferred: ↓"),
+            Span::styled(
+                format_transfer(app.session_totals.inbound_bytes, app.session_totals.inbound_packets),
+                Style::default().fg(Color::Green).add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(" ↑"),
+            Span::styled(
+                format_transfer(app.session_totals.outbound_bytes, app.session_totals.outbound_packets),
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(format!(" | Since reset ({} ago): ↓", format_elapsed(app.totals_since_reset.since))),
+            Span::styled(
+                format_transfer(app.totals_since_reset.inbound_bytes, app.totals_since_reset.inbound_packets),
+                Style::default().fg(Color::Green).add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(" ↑"),
+            Span::styled(
+                format_transfer(app.totals_since_reset.outbound_bytes, app.totals_since_reset.outbound_packets),
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            ),
+        ]),
+        Line::from(vec![
+            Span::raw("Flows: "),
+            Span::styled(
+                format!("{}", app.active_flows),
+                Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(" | Fragmented datagrams: "),
+            Span::styled(
+                format!("{}", app.fragments.fragmented_datagrams),
+                Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(format!(" (total {})", app.total_fragments.fragmented_datagrams)),
+            Span::raw(" | Reassembly timeouts: "),
+            Span::styled(
+                format!("{}", app.fragments.reassembly_timeouts),
+                Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(format!(" (total {})", app.total_fragments.reassembly_timeouts)),
+            Span::raw(" | Untracked fragments: "),
+            Span::styled(
+                format!("{}", app.fragments.overflow_drops),
+                Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(format!(" (total {})", app.total_fragments.overflow_drops)),
+            Span::raw(" | 'p' Mbps/pps | 'g' goodput | 'r' raw | 'b' bursts | 't' range | 'c' reset totals | 'h' sizes | 'l' link scale | 'q' quit"),
+        ]),
+    ];
+    if app.kernel.is_some() || app.capture_drops.is_some() {
+        statistics.insert(statistics.len() - 1, kernel_line(app.kernel.as_ref(), app.capture_drops));
+    }
+    if !app.series_names.is_empty() {
+        statistics.insert(statistics.len() - 1, series_line(app));
+    }
+    if let Some(quota) = &app.quota {
+        // Above the key hints, which stay on the last line
+        statistics.insert(statistics.len() - 1, quota_line(quota));
+    }
+    if let Some(alerts) = &app.alerts {
+        statistics.insert(statistics.len() - 1, alert_line(alerts, app.alerts_fired));
+    }
+    let current_info = Paragraph::new(statistics)
+        .block(Block::default().borders(Borders::ALL).title("Statistics"));
     
+    f.render_widget(current_info, chunks[2]);
+}
+
+fn render_history_chart(f: &mut Frame, area: Rect, tier: &HistoryTier) {
+    let now = SystemTime::now();
+    let span = tier.retention.span.as_secs_f64();
+
+    // x is the age in seconds (negative), so the right edge is always now
+    let series = |rate: fn(&HistoryPoint) -> f64| -> Series {
+        tier.points()
+            .iter()
+            .map(|point| {
+                let age = now.duration_since(point.timestamp).unwrap_or_default().as_secs_f64();
+                (-age, rate(point) * 8.0 / 1_000_000.0)
+            })
+            .collect()
+    };
+    let inbound_avg = series(|point| point.inbound.avg);
+    let outbound_avg = series(|point| point.outbound.avg);
+    let inbound_min = series(|point| point.inbound.min);
+    let outbound_min = series(|point| point.outbound.min);
+    let inbound_max = series(|point| point.inbound.max);
+    let outbound_max = series(|point| point.outbound.max);
+
+    let max_mbps = inbound_max
+        .iter()
+        .chain(outbound_max.iter())
+        .map(|(_, mbps)| *mbps)
+        .fold(0.0, f64::max);
+    let (y_max, y_labels) = bandwidth_axis(max_mbps);
+
+    // Averages as lines, the min/max envelope as dots around them
+    let datasets = vec![
+        Dataset::default()
+            .name("Inbound avg (Mbps)")
+            .marker(symbols::Marker::Braille)
+            .style(Style::default().fg(Color::Green))
+            .graph_type(GraphType::Line)
+            .data(&inbound_avg),
+        Dataset::default()
+            .name("Outbound avg (Mbps)")
+            .marker(symbols::Marker::Braille)
+            .style(Style::default().fg(Color::Red))
+            .graph_type(GraphType::Line)
+            .data(&outbound_avg),
+        Dataset::default()
+            .name("In min/max")
+            .marker(symbols::Marker::Dot)
+            .style(Style::default().fg(Color::LightGreen))
+            .graph_type(GraphType::Scatter)
+            .data(&inbound_max),
+        Dataset::default()
+            .marker(symbols::Marker::Dot)
+            .style(Style::default().fg(Color::LightGreen))
+            .graph_type(GraphType::Scatter)
+            .data(&inbound_min),
+        Dataset::default()
+            .name("Out min/max")
+            .marker(symbols::Marker::Dot)
+            .style(Style::default().fg(Color::LightRed))
+            .graph_type(GraphType::Scatter)
+            .data(&outbound_max),
+        Dataset::default()
+            .marker(symbols::Marker::Dot)
+            .style(Style::default().fg(Color::LightRed))
+            .graph_type(GraphType::Scatter)
+            .data(&outbound_min),
+    ];
+
+    let chart = Chart::new(datasets)
+        .block(
+            Block::default()
+                .title(format!(
+                    "Bandwidth, last {} ({} min/avg/max, {} points)",
+                    format_span(tier.retention.span),
+                    format_span(tier.retention.resolution),
+                    tier.points().len()
+                ))
+                .borders(Borders::ALL),
+        )
+        .x_axis(
+            Axis::default()
+                .title("Time")
+                .style(Style::default().fg(Color::Gray))
+                .bounds([-span, 0.0])
+                .labels(vec![
+                    Span::styled(format!("-{}", format_span(tier.retention.span)), Style::default().add_modifier(Modifier::BOLD)),
+                    Span::styled(format!("-{}", format_span(tier.retention.span / 2)), Style::default().add_modifier(Modifier::BOLD)),
+                    Span::styled("now", Style::default().add_modifier(Modifier::BOLD)),
+                ]),
+        )
+        .y_axis(
+            Axis::default()
+                .title("Mbps")
+                .style(Style::default().fg(Color::Gray))
+                .bounds([0.0, y_max])
+                .labels(y_labels),
+        );
+
+    f.render_widget(chart, area);
+}
+
+fn quota_line(quota: &QuotaStatus) -> Line<'static> {
+    let color = if quota.used_percent() >= 100.0 {
+        Color::Red
+    } else if quota.warning.is_some() {
+        Color::Yellow
+    } else {
+        Color::Green
+    };
+
+    let mut spans = vec![
+        Span::raw(format!("Quota ({}, {}): ", quota.period.name(), quota.direction.name())),
+        Span::styled(
+            format!("{} of {} ({:.1}%)", format_bytes(quota.used_bytes), format_bytes(quota.limit_bytes), quota.used_percent()),
+            Style::default().fg(color).add_modifier(Modifier::BOLD),
+        ),
+        Span::raw(format!(
+            ", {} left until {}",
+            format_bytes(quota.remaining_bytes()),
+            format_utc(quota.period_end)
+        )),
+        Span::raw(" | At this pace: "),
+        match quota.projected_exhaustion {
+            Some(exhaustion) => Span::styled(
+                format!("exhausted {}", format_utc(exhaustion)),
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            ),
+            None => Span::styled("lasts the period", Style::default().fg(Color::Green)),
+        },
+    ];
+    if let Some(percent) = quota.warning {
+        spans.push(Span::styled(
+            format!(" | ⚠ {:.0}% of quota used", percent),
+            Style::default().fg(color).add_modifier(Modifier::BOLD),
+        ));
+    }
+    if let Some(error) = &quota.save_error {
+        spans.push(Span::styled(
+            format!(" | State not saved: {}", error),
+            Style::default().fg(Color::Red),
+        ));
+    }
+
+    Line::from(spans)
+}
+
+// Share of the link in use, from the wire-level rate whatever --layer is; each direction of a
+// full-duplex link has the whole capacity
+fn utilization_span(rate: f64, link_speed: Option<f64>) -> Span<'static> {
+    let Some(link_speed) = link_speed else {
+        return Span::raw("");
+    };
+    let percent = rate / link_speed * 100.0;
+    let color = if percent >= 90.0 {
+        Color::Red
+    } else if percent >= 70.0 {
+        Color::Yellow
+    } else {
+        Color::Gray
+    };
+    Span::styled(format!(" ({:.1}%)", percent), Style::default().fg(color))
+}
+
+fn forecast_span(app: &App) -> Span<'static> {
+    let Some((_, update_interval)) = app.forecast_horizon else {
+        return Span::raw("");
+    };
+    if let Some((direction, eta)) = app.saturation() {
+        return Span::styled(
+            format!(" | ⚠ link will saturate in {} ({})", format_eta(eta), direction),
+            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+        );
+    }
+
+    // Trend per minute, whatever the interval
+    let per_minute = |forecast: Option<Forecast>| {
+        forecast.map_or("-".to_string(), |forecast| {
+            format!("{:+.2}", forecast.trend * 8.0 / 1_000_000.0 * 60.0 / update_interval.as_secs_f64())
+        })
+    };
+    Span::raw(format!(
+        " | Trend: ↓{} ↑{} Mbps/min",
+        per_minute(app.inbound_forecast),
+        per_minute(app.outbound_forecast)
+    ))
+}
+
+fn format_eta(eta: Duration) -> String {
+    let minutes = eta.as_secs_f64() / 60.0;
+    if minutes < 1.0 {
+        "under a minute".to_string()
+    } else if minutes < 120.0 {
+        format!("~{:.0} min", minutes)
+    } else {
+        format!("~{:.1} h", minutes / 60.0)
+    }
+}
+
+fn packet_detail(app: &App, value: String) -> String {
+    if app.packet_details { value } else { "-".to_string() }
+}
+
+// Kernel counters and pcap statistics: where packets that never reached the chart were lost
+fn kernel_line(kernel: Option<&KernelComparison>, capture_drops: Option<CaptureDropCounts>) -> Line<'static> {
+    let count = |text: String, count: u64| {
+        if count > 0 {
+            Span::styled(text, Style::default().fg(Color::Red).add_modifier(Modifier::BOLD))
+        } else {
+            Span::raw(text)
+        }
+    };
+
+    let mut spans = Vec::new();
+    if let Some(kernel) = kernel {
+        spans.extend([
+            Span::raw("Kernel: ↓"),
+            Span::styled(
+                format!("{:.2}", kernel.inbound * 8.0 / 1_000_000.0),
+                Style::default().fg(Color::Green).add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(" ↑"),
+            Span::styled(
+                format!("{:.2}", kernel.outbound * 8.0 / 1_000_000.0),
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(" Mbps | Filter matched "),
+            kernel.matched.map_or(Span::raw("-"), |percent| {
+                Span::styled(format!("{:.1}%", percent), Style::default().fg(Color::White).add_modifier(Modifier::BOLD))
+            }),
+            Span::raw(" of packets | "),
+        ]);
+    }
+    // Lost by the capture, after the kernel counted them
+    if let Some(drops) = capture_drops {
+        spans.push(Span::raw("Capture drops: "));
+        spans.push(count(format!("{}", drops.dropped), drops.dropped));
+        spans.push(Span::raw(" (interface "));
+        spans.push(count(format!("{}", drops.interface_dropped), drops.interface_dropped));
+        spans.push(Span::raw(")"));
+    }
+    // Lost by the NIC or the qdisc, before either of them
+    if let Some(kernel) = kernel {
+        if capture_drops.is_some() {
+            spans.push(Span::raw(" | "));
+        }
+        spans.push(Span::raw("Interface drops: "));
+        spans.push(count(format!("rx {} tx {}", kernel.rx_dropped, kernel.tx_dropped), kernel.rx_dropped + kernel.tx_dropped));
+    }
+
+    Line::from(spans)
+}
+
+fn series_line(app: &App) -> Line<'static> {
+    let mut spans = vec![Span::raw("Series:")];
+    for ((name, rate), color) in app.series_names.iter().zip(&app.current_series).zip(SERIES_COLORS) {
+        spans.push(Span::raw(format!(" {} ", name)));
+        spans.push(Span::styled(
+            format!("{:.2} Mbps", rate * 8.0 / 1_000_000.0),
+            Style::default().fg(color).add_modifier(Modifier::BOLD),
+        ));
+    }
+
+    Line::from(spans)
+}
+
+fn alert_line(alerts: &AlertStatus, fired: u64) -> Line<'static> {
+    let mut spans = vec![
+        Span::raw("Alerts: "),
+        if alerts.firing.is_empty() {
+            Span::styled("none firing", Style::default().fg(Color::Green).add_modifier(Modifier::BOLD))
+        } else {
+            Span::styled(
+                format!("FIRING {}", alerts.firing.join(", ")),
+                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+            )
+        },
+        Span::raw(format!(" | Fired since start: {}", fired)),
+    ];
+    if let Some(error) = &alerts.hook_error {
+        spans.push(Span::styled(format!(" | {}", error), Style::default().fg(Color::Red)));
+    }
+
+    Line::from(spans)
+}
+
+fn format_span(duration: Duration) -> String {
+    let secs = duration.as_secs_f64();
+    let (value, unit) = if secs >= 86400.0 {
+        (secs / 86400.0, "d")
+    } else if secs >= 3600.0 {
+        (secs / 3600.0, "h")
+    } else if secs >= 60.0 {
+        (secs / 60.0, "m")
+    } else if secs >= 1.0 {
+        (secs, "s")
+    } else {
+        (secs * 1000.0, "ms")
+    };
+
+    if value.fract() == 0.0 {
+        format!("{:.0}{}", value, unit)
+    } else {
+        format!("{:.1}{}", value, unit)
+    }
+}
+
+/// Mean and percentiles in Mbps, separated by slashes.
+pub fn format_summary(summary: &RateSummary) -> String {
+    [summary.mean, summary.p50, summary.p90, summary.p95, summary.p99]
+        .iter()
+        .map(|rate| format!("{:.2}", rate * 8.0 / 1_000_000.0))
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Byte count in decimal units, like the Mbps rates.
+pub fn format_bytes(bytes: u64) -> String {
+    let bytes = bytes as f64;
+    if bytes >= 1e12 {
+        format!("{:.2} TB", bytes / 1e12)
+    } else if bytes >= 1e9 {
+        format!("{:.2} GB", bytes / 1e9)
+    } else if bytes >= 1e6 {
+        format!("{:.2} MB", bytes / 1e6)
+    } else if bytes >= 1e3 {
+        format!("{:.2} kB", bytes / 1e3)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
+pub fn format_transfer(bytes: u64, packets: u64) -> String {
+    format!("{} ({} pkts)", format_bytes(bytes), format_count(packets as f64))
+}
+
+fn format_elapsed(since: SystemTime) -> String {
+    let secs = since.elapsed().unwrap_or_default().as_secs();
+    if secs >= 3600 {
+        format!("{}h{:02}m", secs / 3600, secs % 3600 / 60)
+    } else if secs >= 60 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+fn latest_burst(data: &VecDeque<(f64, BurstPeaks)>) -> BurstPeaks {
+    data.back().map(|(_, peaks)| *peaks).unwrap_or_default()
+}
+
+fn format_window(bytes: u32) -> String {
+    if bytes >= 1024 * 1024 {
+        format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+    } else if bytes >= 1024 {
+        format!("{:.1} KiB", bytes as f64 / 1024.0)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
+// Fixed to the link speed, so the evenly spaced labels land on 0/25/50/75/100%
+fn capacity_axis(link_mbps: f64) -> (f64, Vec<Span<'static>>) {
+    let labels = [0.0, 0.25, 0.5, 0.75, 1.0]
+        .iter()
+        .map(|share| {
+            let mbps = link_mbps * share;
+            let mbps = if mbps.fract() == 0.0 { format!("{:.0}", mbps) } else { format!("{:.1}", mbps) };
+            Span::styled(
+                format!("{} ({:.0}%)", mbps, share * 100.0),
+                Style::default().add_modifier(Modifier::BOLD),
+            )
+        })
+        .collect();
+    (link_mbps, labels)
+}
+
+fn bandwidth_axis(max_mbps: f64) -> (f64, Vec<Span<'static>>) {
     // Determine appropriate scale based on current speeds
     let y_max = if max_mbps < 10.0 {
         10.0
@@ -269,59 +1585,33 @@ fn ui(f: &mut Frame, app: &App) {
         ]
     };
 
-    let chart = Chart::new(datasets)
-        .block(
-            Block::default()
-                .title("Bandwidth Over Time")
-                .borders(Borders::ALL),
-        )
-        .x_axis(
-            Axis::default()
-                .title("Time")
-                .style(Style::default().fg(Color::Gray))
-                .bounds([x_min, x_max])
-                .labels(vec![
-                    Span::styled(format!("{:.0}", x_min), Style::default().add_modifier(Modifier::BOLD)),
-                    Span::styled(format!("{:.0}", (x_min + x_max) / 2.0), Style::default().add_modifier(Modifier::BOLD)),
-                    Span::styled(format!("{:.0}", x_max), Style::default().add_modifier(Modifier::BOLD)),
-                ]),
-        )
-        .y_axis(
-            Axis::default()
-                .title("Mbps")
-                .style(Style::default().fg(Color::Gray))
-                .bounds([0.0, y_max])
-                .labels(y_labels),
-        );
+    (y_max, y_labels)
+}
 
-    f.render_widget(chart, chunks[1]);
+fn packet_rate_axis(max_pps: f64) -> (f64, Vec<Span<'static>>) {
+    // Round up to the next 1/2/5 step so the axis labels stay readable
+    let magnitude = 10f64.powf(max_pps.max(1.0).log10().floor());
+    let y_max = [1.0, 2.0, 5.0, 10.0]
+        .iter()
+        .map(|step| step * magnitude)
+        .find(|candidate| *candidate >= max_pps)
+        .unwrap_or(10.0 * magnitude)
+        .max(10.0);
 
-    let current_info = Paragraph::new(vec![
-        Line::from(vec![
-            Span::raw("↓ In: "),
-            Span::styled(
-                format!("{:.2} Mbps", current_inbound_mbps),
-                Style::default().fg(Color::Green).add_modifier(Modifier::BOLD),
-            ),
-            Span::raw(" | ↑ Out: "),
-            Span::styled(
-                format!("{:.2} Mbps", current_outbound_mbps),
-                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
-            ),
-            Span::raw(" | Max: ↓"),
-            Span::styled(
-                format!("{:.1}", max_inbound_mbps),
-                Style::default().fg(Color::Green).add_modifier(Modifier::BOLD),
-            ),
-            Span::raw(" ↑"),
-            Span::styled(
-                format!("{:.1}", max_outbound_mbps),
-                Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
-            ),
-            Span::raw(" | Press 'q' to quit"),
-        ]),
-    ])
-    .block(Block::default().borders(Borders::ALL).title("Statistics"));
-    
-    f.render_widget(current_info, chunks[2]);
-}
\ No newline at end of file
+    let step = y_max / 4.0;
+    let y_labels = (0..=4)
+        .map(|i| Span::styled(format_count(step * i as f64), Style::default().add_modifier(Modifier::BOLD)))
+        .collect();
+
+    (y_max, y_labels)
+}
+
+fn format_count(value: f64) -> String {
+    if value >= 1_000_000.0 {
+        format!("{:.1}M", value / 1_000_000.0)
+    } else if value >= 1_000.0 {
+        format!("{:.1}k", value / 1_000.0)
+    } else {
+        format!("{:.0}", value)
+    }
+}
@@ -1,6 +1,38 @@
-use tcpgraph::bandwidth::BandwidthCalculator;
-use tcpgraph::capture::{PacketInfo, TrafficDirection};
-use std::time::{Duration, SystemTime};
+use tcpgraph::bandwidth::{AVERAGE_WINDOWS, BandwidthCalculator, MonitorCommand, PacketSizeHistogram, RateDistribution, RateSummary, Smoothing};
+use tcpgraph::capture::{AccountingLayer, CaptureDropCounts, CaptureDrops, FlowKey, PacketCapture, PacketInfo, TrafficDirection};
+use tcpgraph::fragment::{FragmentStats, FragmentTracker};
+use tcpgraph::tcp_window::{TcpSegment, TcpWindowInfo, TcpWindowTracker, WindowEvent};
+use pnet::packet::ip::IpNextHeaderProtocols;
+use pnet::packet::tcp::TcpFlags;
+use pnet::util::MacAddr;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tcpgraph::cli::{parse_alert, parse_bytes, parse_format, parse_interval, parse_rate, parse_retention, parse_series};
+use tcpgraph::calendar::{format_rfc3339, format_utc};
+use tcpgraph::quota::{QuotaConfig, QuotaDirection, QuotaPeriod, QuotaTracker};
+use tcpgraph::history::{RateRange, RetentionTier, TieredHistory};
+use tcpgraph::fragment::{FragmentKey, IpFragment};
+use tcpgraph::alert::{AlertEngine, AlertMetric, AlertState, Comparison};
+use tcpgraph::forecast::{Forecast, MIN_FORECAST_SAMPLES};
+use tcpgraph::anomaly::{AnomalyDetector, Baseline, BaselineMethod};
+use tcpgraph::counters::{CounterSampler, CounterSource, InterfaceCounters};
+use tcpgraph::output::{DEFAULT_FORMAT, FormatPiece, OutputField};
+
+fn test_packet(size: u32, direction: TrafficDirection) -> PacketInfo {
+    PacketInfo {
+        timestamp: SystemTime::now(),
+        size,
+        frame_size: size,
+        payload_size: size,
+        direction,
+        flow: None,
+        fragments: FragmentStats::default(),
+        tcp_window: None,
+        series: 0,
+    }
+}
 
 #[test]
 fn test_bandwidth_calculator_empty() {
@@ -17,7 +49,13 @@ fn test_bandwidth_calculator_single_packet() {
     let packet = PacketInfo {
         timestamp: SystemTime::now(),
         size: 1000,
+        frame_size: 1000,
+        payload_size: 1000,
         direction: TrafficDirection::Inbound,
+        flow: None,
+        fragments: FragmentStats::default(),
+        tcp_window: None,
+        series: 0,
     };
     
     calc.add_packet(packet);
@@ -37,7 +75,13 @@ fn test_bandwidth_calculator_multiple_packets() {
         let packet = PacketInfo {
             timestamp: now,
             size: 200,
+            frame_size: 200,
+            payload_size: 200,
             direction: if i % 2 == 0 { TrafficDirection::Inbound } else { TrafficDirection::Outbound },
+            flow: None,
+            fragments: FragmentStats::default(),
+            tcp_window: None,
+            series: 0,
         };
         calc.add_packet(packet);
     }
@@ -55,4 +99,1469 @@ fn test_bandwidth_calculator_history_limit() {
     }
     
     assert!(calc.get_history().len() <= 2);
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_forecast_needs_enough_history() {
+    // The history never holds enough samples for a forecast
+    let mut calc = BandwidthCalculator::new(Duration::from_secs(1), MIN_FORECAST_SAMPLES - 1, 1).with_forecast();
+    for _ in 0..2 * MIN_FORECAST_SAMPLES {
+        assert_eq!(calc.calculate_bandwidth().inbound_forecast, None);
+    }
+
+    let mut calc = BandwidthCalculator::new(Duration::from_secs(1), MIN_FORECAST_SAMPLES, 1).with_forecast();
+    for _ in 0..2 * MIN_FORECAST_SAMPLES {
+        calc.calculate_bandwidth();
+    }
+    assert!(calc.calculate_bandwidth().inbound_forecast.is_some());
+}
+
+fn client_flow() -> FlowKey {
+    FlowKey {
+        protocol: IpNextHeaderProtocols::Tcp,
+        src: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+        src_port: 40000,
+        dst: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+        dst_port: 80,
+    }
+}
+
+fn segment(flow: FlowKey, sequence: u32, acknowledgement: u32, window: u16, payload_len: u32) -> TcpSegment {
+    TcpSegment {
+        flow,
+        sequence,
+        acknowledgement,
+        flags: TcpFlags::ACK,
+        window,
+        window_scale: None,
+        payload_len,
+    }
+}
+
+fn handshake(tracker: &mut TcpWindowTracker, client: FlowKey, window_scale: Option<u8>) {
+    let mut syn = segment(client, 0, 0, 64240, 0);
+    syn.flags = TcpFlags::SYN;
+    syn.window_scale = window_scale;
+    tracker.observe(&syn, SystemTime::now());
+
+    let mut syn_ack = segment(client.reversed(), 0, 1, 65160, 0);
+    syn_ack.flags = TcpFlags::SYN | TcpFlags::ACK;
+    syn_ack.window_scale = window_scale;
+    tracker.observe(&syn_ack, SystemTime::now());
+}
+
+#[test]
+fn test_tcp_window_zero_window() {
+    let mut tracker = TcpWindowTracker::new();
+    let server = client_flow().reversed();
+
+    // Reported even for a connection whose handshake was missed
+    let info = tracker.observe(&segment(server, 1, 1, 0, 0), SystemTime::now());
+    assert_eq!(info.advertised_window, None);
+    assert_eq!(info.event, Some(WindowEvent::ZeroWindow));
+}
+
+#[test]
+fn test_tcp_window_full() {
+    let mut tracker = TcpWindowTracker::new();
+    let client = client_flow();
+    let server = client.reversed();
+    handshake(&mut tracker, client, None);
+
+    // Server acknowledges up to 1000 and advertises 2000 bytes of window
+    tracker.observe(&segment(server, 1, 1000, 2000, 0), SystemTime::now());
+
+    let info = tracker.observe(&segment(client, 1000, 1, 65535, 1000), SystemTime::now());
+    assert_eq!(info.event, None);
+
+    let info = tracker.observe(&segment(client, 2000, 1, 65535, 1000), SystemTime::now());
+    assert_eq!(info.event, Some(WindowEvent::WindowFull));
+}
+
+#[test]
+fn test_tcp_window_scaling() {
+    let mut tracker = TcpWindowTracker::new();
+    let client = client_flow();
+    let server = client.reversed();
+
+    let mut syn = segment(client, 0, 0, 64240, 0);
+    syn.flags = TcpFlags::SYN;
+    syn.window_scale = Some(7);
+    tracker.observe(&syn, SystemTime::now());
+
+    let mut syn_ack = segment(server, 0, 1, 65160, 0);
+    syn_ack.flags = TcpFlags::SYN | TcpFlags::ACK;
+    syn_ack.window_scale = Some(7);
+    assert_eq!(tracker.observe(&syn_ack, SystemTime::now()).advertised_window, Some(65160));
+
+    let info = tracker.observe(&segment(client, 1, 1, 502, 0), SystemTime::now());
+    assert_eq!(info.advertised_window, Some(502 << 7));
+}
+
+#[test]
+fn test_tcp_window_half_close_keeps_scaling() {
+    let mut tracker = TcpWindowTracker::new();
+    let client = client_flow();
+    let server = client.reversed();
+    handshake(&mut tracker, client, Some(7));
+
+    let mut fin = segment(client, 1, 1, 502, 0);
+    fin.flags = TcpFlags::FIN | TcpFlags::ACK;
+    tracker.observe(&fin, SystemTime::now());
+
+    // The client still acknowledges the server's data with a scaled window
+    assert_eq!(tracker.observe(&segment(client, 2, 1, 502, 0), SystemTime::now()).advertised_window, Some(502 << 7));
+
+    let mut fin = segment(server, 1, 2, 509, 0);
+    fin.flags = TcpFlags::FIN | TcpFlags::ACK;
+    tracker.observe(&fin, SystemTime::now());
+
+    // The final ACK doesn't bring the closed connection back
+    assert_eq!(tracker.observe(&segment(client, 2, 2, 502, 0), SystemTime::now()).advertised_window, None);
+}
+
+#[test]
+fn test_tcp_window_closed_connections_are_forgotten() {
+    let mut tracker = TcpWindowTracker::new();
+
+    // More closed connections than the table holds
+    for port in 0..20_000u32 {
+        let client = FlowKey { src_port: 1024 + (port % 60_000) as u16, dst_port: 80 + (port / 60_000) as u16, ..client_flow() };
+        handshake(&mut tracker, client, Some(7));
+        for (flow, ack) in [(client, 1), (client.reversed(), 2)] {
+            let mut fin = segment(flow, 1, ack, 502, 0);
+            fin.flags = TcpFlags::FIN | TcpFlags::ACK;
+            tracker.observe(&fin, SystemTime::now());
+        }
+        tracker.observe(&segment(client, 2, 2, 502, 0), SystemTime::now());
+    }
+
+    let client = FlowKey { src_port: 65000, ..client_flow() };
+    handshake(&mut tracker, client, Some(7));
+    assert_eq!(tracker.observe(&segment(client, 1, 1, 502, 0), SystemTime::now()).advertised_window, Some(502 << 7));
+}
+
+#[test]
+fn test_tcp_window_evicts_idle_endpoints_when_full() {
+    let mut tracker = TcpWindowTracker::new();
+    let long_ago = SystemTime::now() - Duration::from_secs(3600);
+
+    // Connections that were never closed fill the table
+    for port in 0..20_000u32 {
+        let mut syn = segment(FlowKey { src_port: 1024 + port as u16, ..client_flow() }, 0, 0, 64240, 0);
+        syn.flags = TcpFlags::SYN;
+        syn.window_scale = Some(7);
+        tracker.observe(&syn, long_ago);
+    }
+
+    let client = FlowKey { src_port: 65000, ..client_flow() };
+    handshake(&mut tracker, client, Some(7));
+    assert_eq!(tracker.observe(&segment(client, 1, 1, 502, 0), SystemTime::now()).advertised_window, Some(502 << 7));
+}
+
+#[test]
+fn test_tcp_window_evicts_oldest_endpoints_when_full() {
+    let mut tracker = TcpWindowTracker::new();
+    let start = SystemTime::now() - Duration::from_secs(60);
+    let client = |port: u32| FlowKey { src_port: 1024 + port as u16, ..client_flow() };
+
+    // Active connections overflow the table, so the ones silent the longest make room
+    for port in 0..20_000u32 {
+        let mut syn = segment(client(port), 0, 0, 64240, 0);
+        syn.flags = TcpFlags::SYN;
+        tracker.observe(&syn, start + Duration::from_millis(port as u64));
+    }
+
+    assert_eq!(tracker.observe(&segment(client(0), 1, 1, 502, 0), SystemTime::now()).advertised_window, None);
+    assert_eq!(tracker.observe(&segment(client(19_999), 1, 1, 502, 0), SystemTime::now()).advertised_window, Some(502));
+}
+
+#[test]
+fn test_bandwidth_calculator_window_events() {
+    let mut calc = BandwidthCalculator::new(Duration::from_secs(1), 100, 1);
+
+    // The window of a flow whose handshake was missed is unknown and left out of the minimum
+    for (advertised_window, event) in [
+        (None, Some(WindowEvent::ZeroWindow)),
+        (Some(4096), Some(WindowEvent::WindowFull)),
+        (Some(8192), None),
+    ] {
+        calc.add_packet(PacketInfo {
+            timestamp: SystemTime::now(),
+            size: 60,
+            frame_size: 60,
+            payload_size: 60,
+            direction: TrafficDirection::Inbound,
+            flow: None,
+            fragments: FragmentStats::default(),
+            tcp_window: Some(TcpWindowInfo { advertised_window, event }),
+            series: 0,
+        });
+    }
+
+    let bandwidth = calc.calculate_bandwidth();
+    assert_eq!(bandwidth.tcp_windows.zero_window_events, 1);
+    assert_eq!(bandwidth.tcp_windows.window_full_events, 1);
+    assert_eq!(bandwidth.tcp_windows.min_advertised_window, Some(4096));
+}
+
+#[test]
+fn test_bandwidth_calculator_packet_rate() {
+    let mut calc = BandwidthCalculator::new(Duration::from_secs(1), 100, 1);
+    let now = SystemTime::now();
+
+    for direction in [TrafficDirection::Inbound, TrafficDirection::Inbound, TrafficDirection::Outbound] {
+        calc.add_packet(PacketInfo {
+            timestamp: now,
+            size: 64,
+            frame_size: 64,
+            payload_size: 64,
+            direction,
+            flow: None,
+            fragments: FragmentStats::default(),
+            tcp_window: None,
+            series: 0,
+        });
+    }
+
+    let bandwidth = calc.calculate_bandwidth();
+    assert_eq!(bandwidth.inbound_pps, 2.0);
+    assert_eq!(bandwidth.outbound_pps, 1.0);
+    assert_eq!(calc.get_history().back().unwrap().inbound_pps, 2.0);
+}
+
+#[test]
+fn test_packet_size_histogram_buckets() {
+    let mut histogram = PacketSizeHistogram::default();
+    for size in [0, 64, 65, 1500, 1501, 9000, 9001] {
+        histogram.record(size);
+    }
+
+    assert_eq!(histogram.counts, [2, 1, 0, 0, 0, 1, 2, 1]);
+    assert_eq!(histogram.total(), 7);
+    assert_eq!(PacketSizeHistogram::bucket_label(0), "0-64");
+    assert_eq!(PacketSizeHistogram::bucket_label(7), ">9000");
+}
+
+#[test]
+fn test_bandwidth_calculator_size_histogram() {
+    let mut calc = BandwidthCalculator::new(Duration::from_secs(1), 100, 1);
+
+    calc.add_packet(test_packet(1500, TrafficDirection::Inbound));
+    calc.calculate_bandwidth();
+
+    calc.add_packet(test_packet(60, TrafficDirection::Inbound));
+    calc.add_packet(test_packet(1400, TrafficDirection::Inbound));
+    let bandwidth = calc.calculate_bandwidth();
+
+    assert_eq!(bandwidth.size_histogram.total(), 2);
+    assert_eq!(bandwidth.size_histogram.counts[5], 1);
+    assert_eq!(bandwidth.session_size_histogram.total(), 3);
+    assert_eq!(bandwidth.session_size_histogram.counts[5], 2);
+}
+
+#[test]
+fn test_wire_rate_ignores_accounting_layer() {
+    let mut calc = BandwidthCalculator::new(Duration::from_secs(1), 100, 1);
+
+    // Payload-layer accounting of a full frame and of a bare ACK, padded to the minimum on the wire
+    calc.add_packet(PacketInfo { size: 1448, frame_size: 1514, ..test_packet(1448, TrafficDirection::Inbound) });
+    calc.add_packet(PacketInfo { size: 0, frame_size: 54, ..test_packet(0, TrafficDirection::Outbound) });
+    let bandwidth = calc.calculate_bandwidth();
+
+    assert_eq!(bandwidth.inbound, 1448.0);
+    assert_eq!(bandwidth.inbound_wire, 1538.0);
+    assert_eq!(bandwidth.outbound_wire, 84.0);
+}
+
+#[test]
+fn test_size_histogram_uses_frame_length() {
+    let mut calc = BandwidthCalculator::new(Duration::from_secs(1), 100, 1);
+
+    // Accounted at the payload layer, but the frame is still a full-sized one
+    calc.add_packet(PacketInfo { size: 1448, frame_size: 1514, ..test_packet(1448, TrafficDirection::Inbound) });
+    let bandwidth = calc.calculate_bandwidth();
+
+    assert_eq!(bandwidth.inbound, 1448.0);
+    assert_eq!(bandwidth.size_histogram.counts[5], 0);
+    assert_eq!(bandwidth.size_histogram.counts[6], 1);
+}
+
+fn ethernet_frame(ethertype: u16, payload: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0u8; 12];
+    frame.extend_from_slice(&ethertype.to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+fn ipv4_packet(protocol: u8, transport: &[u8]) -> Vec<u8> {
+    let mut packet = vec![0u8; 20];
+    packet[0] = 0x45;
+    packet[2..4].copy_from_slice(&((20 + transport.len()) as u16).to_be_bytes());
+    packet[8] = 64;
+    packet[9] = protocol;
+    packet.extend_from_slice(transport);
+    ethernet_frame(0x0800, &packet)
+}
+
+fn ipv6_packet(next_header: u8, payload: &[u8]) -> Vec<u8> {
+    let mut packet = vec![0u8; 40];
+    packet[0] = 0x60;
+    packet[4..6].copy_from_slice(&(payload.len() as u16).to_be_bytes());
+    packet[6] = next_header;
+    packet.extend_from_slice(payload);
+    ethernet_frame(0x86dd, &packet)
+}
+
+#[test]
+fn test_payload_size_udp() {
+    let mut udp = vec![0u8; 8];
+    udp.extend_from_slice(&[0xaa; 100]);
+    assert_eq!(PacketCapture::get_payload_size(&ipv4_packet(17, &udp)), 100);
+}
+
+#[test]
+fn test_payload_size_ipv6_extension_headers() {
+    // Hop-by-hop (8 bytes) -> fragment (8 bytes) -> TCP (20 bytes) -> 50 bytes of data
+    let mut payload = vec![44, 0, 0, 0, 0, 0, 0, 0];
+    payload.extend_from_slice(&[6, 0, 0, 0, 0, 0, 0, 1]);
+    let mut tcp = vec![0u8; 20];
+    tcp[12] = 5 << 4;
+    payload.extend_from_slice(&tcp);
+    payload.extend_from_slice(&[0xbb; 50]);
+
+    assert_eq!(PacketCapture::get_payload_size(&ipv6_packet(0, &payload)), 50);
+}
+
+#[test]
+fn test_payload_size_icmpv6() {
+    let mut icmp = vec![128, 0, 0, 0, 0, 1, 0, 1];
+    icmp.extend_from_slice(&[0xcc; 56]);
+    assert_eq!(PacketCapture::get_payload_size(&ipv6_packet(58, &icmp)), 56);
+}
+
+#[test]
+fn test_payload_size_sctp_counts_only_data_chunks() {
+    let mut sctp = vec![0u8; 12];
+    // DATA chunk: 16-byte header + 30 bytes of user data, padded to 48
+    sctp.extend_from_slice(&[0, 0, 0, 46]);
+    sctp.extend_from_slice(&[0u8; 12]);
+    sctp.extend_from_slice(&[0xdd; 32]);
+    // SACK chunk carries no user data
+    sctp.extend_from_slice(&[3, 0, 0, 16]);
+    sctp.extend_from_slice(&[0u8; 12]);
+
+    assert_eq!(PacketCapture::get_payload_size(&ipv4_packet(132, &sctp)), 30);
+}
+
+#[test]
+fn test_payload_size_sctp_bounded_by_ip_length() {
+    // A lone SACK chunk; the Ethernet padding behind it happens to look like a DATA chunk
+    let mut sctp = vec![0u8; 12];
+    sctp.extend_from_slice(&[3, 0, 0, 16]);
+    sctp.extend_from_slice(&[0u8; 12]);
+    let mut frame = ipv4_packet(132, &sctp);
+    frame.extend_from_slice(&[0, 0, 0, 20]);
+    frame.extend_from_slice(&[0xee; 16]);
+    assert_eq!(PacketCapture::get_payload_size(&frame), 0);
+
+    // A short snaplen cut the frame off before the DATA chunk behind the SACK
+    let mut sctp = vec![0u8; 12];
+    sctp.extend_from_slice(&[3, 0, 0, 16]);
+    sctp.extend_from_slice(&[0u8; 12]);
+    sctp.extend_from_slice(&[0, 0, 0, 48]);
+    sctp.extend_from_slice(&[0u8; 12]);
+    sctp.extend_from_slice(&[0xdd; 32]);
+    let frame = ipv4_packet(132, &sctp);
+    let snaplen = frame.len() - 48;
+    assert_eq!(PacketCapture::get_payload_size(&frame[..snaplen]), 32);
+}
+
+#[test]
+fn test_accounted_size_per_layer() {
+    let mut udp = vec![0u8; 8];
+    udp.extend_from_slice(&[0xaa; 100]);
+    let frame = ipv4_packet(17, &udp);
+    let frame_len = frame.len() as u32;
+
+    assert_eq!(PacketCapture::get_accounted_size(AccountingLayer::Frame, &frame, frame_len), 142);
+    assert_eq!(PacketCapture::get_accounted_size(AccountingLayer::Ip, &frame, frame_len), 128);
+    assert_eq!(PacketCapture::get_accounted_size(AccountingLayer::Payload, &frame, frame_len), 100);
+    // Frame + FCS (4) + preamble/SFD (8) + inter-frame gap (12)
+    assert_eq!(PacketCapture::get_accounted_size(AccountingLayer::Wire, &frame, frame_len), 166);
+}
+
+#[test]
+fn test_wire_size_pads_runt_frames() {
+    let frame = ipv4_packet(17, &[0u8; 8]);
+    assert_eq!(PacketCapture::get_accounted_size(AccountingLayer::Wire, &frame, frame.len() as u32), 84);
+}
+
+#[test]
+fn test_bandwidth_calculator_goodput_and_overhead() {
+    let mut calc = BandwidthCalculator::new(Duration::from_secs(1), 100, 1);
+
+    for direction in [TrafficDirection::Inbound, TrafficDirection::Outbound] {
+        calc.add_packet(PacketInfo {
+            timestamp: SystemTime::now(),
+            size: 1000,
+            frame_size: 1000,
+            payload_size: 900,
+            direction,
+            flow: None,
+            fragments: FragmentStats::default(),
+            tcp_window: None,
+            series: 0,
+        });
+    }
+
+    let bandwidth = calc.calculate_bandwidth();
+    assert_eq!(bandwidth.inbound, 1000.0);
+    assert_eq!(bandwidth.inbound_goodput, 900.0);
+    assert_eq!(bandwidth.outbound_goodput, 900.0);
+    assert!((bandwidth.overhead_percent() - 10.0).abs() < 1e-9);
+}
+
+fn fragmented_ipv4_udp(identification: u16, fragment_offset: u16, more_fragments: bool, data: &[u8]) -> Vec<u8> {
+    let mut packet = vec![0u8; 20];
+    packet[0] = 0x45;
+    packet[2..4].copy_from_slice(&((20 + data.len()) as u16).to_be_bytes());
+    packet[4..6].copy_from_slice(&identification.to_be_bytes());
+    let flags_and_offset = ((more_fragments as u16) << 13) | (fragment_offset / 8);
+    packet[6..8].copy_from_slice(&flags_and_offset.to_be_bytes());
+    packet[8] = 64;
+    packet[9] = 17;
+    packet[12..16].copy_from_slice(&[10, 0, 0, 1]);
+    packet[16..20].copy_from_slice(&[10, 0, 0, 2]);
+    packet.extend_from_slice(data);
+    ethernet_frame(0x0800, &packet)
+}
+
+#[test]
+fn test_non_first_fragment_is_all_payload() {
+    let frame = fragmented_ipv4_udp(7, 1480, false, &[0xee; 200]);
+    assert_eq!(PacketCapture::get_payload_size(&frame), 200);
+}
+
+#[test]
+fn test_fragments_attributed_to_first_fragment_flow() {
+    let mut tracker = FragmentTracker::new();
+    let now = SystemTime::now();
+
+    let mut first = vec![0u8; 1480];
+    first[0..2].copy_from_slice(&5353u16.to_be_bytes());
+    first[2..4].copy_from_slice(&9999u16.to_be_bytes());
+    let (flow, stats) = PacketCapture::identify_flow(&fragmented_ipv4_udp(7, 0, true, &first), &mut tracker, now);
+    let flow = flow.expect("first fragment carries the UDP ports");
+    assert_eq!((flow.src_port, flow.dst_port), (5353, 9999));
+    assert_eq!(stats.fragmented_datagrams, 1);
+
+    let (second_flow, stats) = PacketCapture::identify_flow(&fragmented_ipv4_udp(7, 1480, false, &[0u8; 200]), &mut tracker, now);
+    assert_eq!(second_flow, Some(flow));
+    assert_eq!(stats.fragmented_datagrams, 0);
+
+    // The datagram is complete, so nothing is left to time out
+    assert_eq!(tracker.expire(now + Duration::from_secs(60)), 0);
+}
+
+#[test]
+fn test_fragment_reassembly_timeout() {
+    let mut tracker = FragmentTracker::new();
+    let now = SystemTime::now();
+    let fragment = IpFragment {
+        key: FragmentKey {
+            src: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+            dst: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+            protocol: IpNextHeaderProtocols::Udp,
+            identification: 42,
+        },
+        offset: 0,
+        more_fragments: true,
+        length: 1480,
+    };
+
+    tracker.observe(&fragment, None, now);
+    assert_eq!(tracker.expire(now + Duration::from_secs(5)), 0);
+    assert_eq!(tracker.expire(now + Duration::from_secs(31)), 1);
+}
+
+#[test]
+fn test_fragment_duplicates_do_not_complete_or_reopen_datagram() {
+    let mut tracker = FragmentTracker::new();
+    let now = SystemTime::now();
+    let fragment = |offset, more_fragments, length| IpFragment {
+        key: FragmentKey {
+            src: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+            dst: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+            protocol: IpNextHeaderProtocols::Udp,
+            identification: 42,
+        },
+        offset,
+        more_fragments,
+        length,
+    };
+
+    // The first fragment twice and the last one: 2960 bytes received, but 1480..2960 is still missing
+    let (_, stats) = tracker.observe(&fragment(0, true, 1480), None, now);
+    assert_eq!(stats.fragmented_datagrams, 1);
+    tracker.observe(&fragment(0, true, 1480), None, now);
+    tracker.observe(&fragment(2960, false, 40), None, now);
+    tracker.observe(&fragment(1480, true, 1480), None, now);
+
+    // Complete now; a late duplicate is neither a new datagram nor a timeout
+    let (_, stats) = tracker.observe(&fragment(1480, true, 1480), None, now + Duration::from_secs(1));
+    assert_eq!(stats.fragmented_datagrams, 0);
+    assert_eq!(tracker.expire(now + Duration::from_secs(60)), 0);
+
+    // Before the middle fragment arrives the datagram is incomplete
+    let mut tracker = FragmentTracker::new();
+    tracker.observe(&fragment(0, true, 1480), None, now);
+    tracker.observe(&fragment(0, true, 1480), None, now);
+    tracker.observe(&fragment(2960, false, 40), None, now);
+    assert_eq!(tracker.expire(now + Duration::from_secs(31)), 1);
+}
+
+#[test]
+fn test_fragment_table_overflow_counted_separately() {
+    let mut tracker = FragmentTracker::new();
+    let now = SystemTime::now();
+    let fragment = |identification| IpFragment {
+        key: FragmentKey {
+            src: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+            dst: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+            protocol: IpNextHeaderProtocols::Udp,
+            identification,
+        },
+        offset: 0,
+        more_fragments: true,
+        length: 1480,
+    };
+
+    for identification in 0..4096 {
+        tracker.observe(&fragment(identification), None, now);
+    }
+
+    // Every fragment of a datagram that doesn't fit is a drop, not a new datagram
+    for _ in 0..3 {
+        let (_, stats) = tracker.observe(&fragment(5000), None, now);
+        assert_eq!(stats.fragmented_datagrams, 0);
+        assert_eq!(stats.overflow_drops, 1);
+    }
+}
+
+#[test]
+fn test_bandwidth_calculator_expires_fragments_without_traffic() {
+    let tracker = Arc::new(Mutex::new(FragmentTracker::new()));
+    let mut calc = BandwidthCalculator::new(Duration::from_secs(1), 100, 1).with_fragment_tracker(Arc::clone(&tracker));
+
+    let fragment = IpFragment {
+        key: FragmentKey {
+            src: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+            dst: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+            protocol: IpNextHeaderProtocols::Udp,
+            identification: 42,
+        },
+        offset: 0,
+        more_fragments: true,
+        length: 1480,
+    };
+    tracker.lock().unwrap().observe(&fragment, None, SystemTime::now() - Duration::from_secs(60));
+
+    assert_eq!(calc.calculate_bandwidth().fragments.reassembly_timeouts, 1);
+    assert_eq!(calc.calculate_bandwidth().fragments.reassembly_timeouts, 0);
+}
+
+#[test]
+fn test_bandwidth_calculator_counts_each_packet_once() {
+    let mut calc = BandwidthCalculator::new(Duration::from_secs(5), 100, 1);
+
+    for _ in 0..10 {
+        calc.add_packet(test_packet(500, TrafficDirection::Inbound));
+    }
+
+    // 5000 bytes over a 5 second interval
+    assert_eq!(calc.calculate_bandwidth().inbound, 1000.0);
+    // The next interval starts empty instead of re-counting the same packets
+    assert_eq!(calc.calculate_bandwidth().inbound, 0.0);
+}
+
+#[test]
+fn test_bandwidth_calculator_separates_directions() {
+    let mut calc = BandwidthCalculator::new(Duration::from_secs(1), 100, 1);
+    calc.add_packet(test_packet(1000, TrafficDirection::Inbound));
+    calc.add_packet(test_packet(200, TrafficDirection::Outbound));
+    calc.add_packet(test_packet(3000, TrafficDirection::Transit));
+    calc.add_packet(test_packet(400, TrafficDirection::Internal));
+    calc.add_packet(test_packet(60, TrafficDirection::Broadcast));
+
+    let bandwidth = calc.calculate_bandwidth();
+    assert_eq!(bandwidth.inbound, 1000.0);
+    assert_eq!(bandwidth.outbound, 200.0);
+    assert_eq!(bandwidth.transit, 3000.0);
+    assert_eq!(bandwidth.internal, 400.0);
+    assert_eq!(bandwidth.broadcast, 60.0);
+    assert_eq!(bandwidth.inbound_pps, 1.0);
+    assert_eq!(bandwidth.total, 1200.0);
+}
+
+#[test]
+fn test_bandwidth_calculator_total_includes_selected_directions() {
+    let mut calc = BandwidthCalculator::new(Duration::from_secs(1), 100, 1).with_total(vec![
+        TrafficDirection::Inbound,
+        TrafficDirection::Transit,
+        TrafficDirection::Transit,
+    ]);
+    calc.add_packet(test_packet(1000, TrafficDirection::Inbound));
+    calc.add_packet(test_packet(200, TrafficDirection::Outbound));
+    calc.add_packet(test_packet(3000, TrafficDirection::Transit));
+
+    assert_eq!(calc.calculate_bandwidth().total, 4000.0);
+}
+
+#[test]
+fn test_determine_direction() {
+    let ours = MacAddr::new(0x02, 0, 0, 0, 0, 1);
+    let other = MacAddr::new(0x02, 0, 0, 0, 0, 2);
+    let second_local = MacAddr::new(0x02, 0, 0, 0, 0, 3);
+    let local_macs: HashSet<MacAddr> = [ours, second_local].into_iter().collect();
+    let frame = |dst: MacAddr, src: MacAddr| {
+        let mut frame = ipv4_packet(17, &[0u8; 8]);
+        frame[0..6].copy_from_slice(&dst.octets());
+        frame[6..12].copy_from_slice(&src.octets());
+        frame
+    };
+
+    let classify = |dst, src| PacketCapture::determine_direction(&frame(dst, src), &local_macs);
+    assert_eq!(classify(ours, other), TrafficDirection::Inbound);
+    assert_eq!(classify(other, ours), TrafficDirection::Outbound);
+    assert_eq!(classify(second_local, ours), TrafficDirection::Internal);
+    assert_eq!(classify(other, MacAddr::new(0x02, 0, 0, 0, 0, 4)), TrafficDirection::Transit);
+    assert_eq!(classify(MacAddr::broadcast(), ours), TrafficDirection::Broadcast);
+    assert_eq!(classify(MacAddr::new(0x01, 0x00, 0x5e, 0, 0, 0xfb), other), TrafficDirection::Broadcast);
+    assert_eq!(PacketCapture::determine_direction(&[0u8; 10], &local_macs), TrafficDirection::Transit);
+}
+
+#[test]
+fn test_bandwidth_calculator_averages_over_window() {
+    let mut calc = BandwidthCalculator::new(Duration::from_secs(3), 100, 1)
+        .with_update_interval(Duration::from_secs(1));
+
+    calc.add_packet(test_packet(3000, TrafficDirection::Inbound));
+    assert_eq!(calc.calculate_bandwidth().inbound, 3000.0);
+    assert_eq!(calc.calculate_bandwidth().inbound, 1500.0);
+    assert_eq!(calc.calculate_bandwidth().inbound, 1000.0);
+    // The burst has now left the three-interval window
+    assert_eq!(calc.calculate_bandwidth().inbound, 0.0);
+}
+
+#[test]
+fn test_bandwidth_calculator_divides_by_measured_span() {
+    let mut calc = BandwidthCalculator::new(Duration::from_secs(1), 100, 1);
+    let start = Instant::now();
+    calc.calculate_bandwidth_at(start);
+
+    // A tick that fired half a second late: 3000 bytes over 1.5 s
+    calc.add_packet(test_packet(3000, TrafficDirection::Inbound));
+    let bandwidth = calc.calculate_bandwidth_at(start + Duration::from_millis(1500));
+    assert_eq!(bandwidth.inbound, 2000.0);
+    // Also counts the instant between creating the calculator and `start`
+    assert!((bandwidth.averages[0].inbound - 2000.0).abs() < 1.0);
+
+    // An early one: 1000 bytes over 0.5 s
+    calc.add_packet(test_packet(1000, TrafficDirection::Inbound));
+    let bandwidth = calc.calculate_bandwidth_at(start + Duration::from_millis(2000));
+    assert_eq!(bandwidth.inbound, 2000.0);
+    assert!((bandwidth.averages[0].inbound - 2000.0).abs() < 1.0);
+}
+
+fn smoothed_series(smoothing: Smoothing, sizes: &[u32]) -> Vec<f64> {
+    let mut calc = BandwidthCalculator::new(Duration::from_secs(1), 100, 1).with_smoothing(smoothing);
+    sizes
+        .iter()
+        .map(|size| {
+            calc.add_packet(test_packet(*size, TrafficDirection::Inbound));
+            calc.calculate_bandwidth().inbound
+        })
+        .collect()
+}
+
+#[test]
+fn test_smoothing_median_ignores_single_outlier() {
+    let series = smoothed_series(Smoothing::Median(3), &[100, 100, 10000, 100]);
+    assert_eq!(series, vec![100.0, 100.0, 100.0, 100.0]);
+}
+
+#[test]
+fn test_smoothing_ewma() {
+    let series = smoothed_series(Smoothing::Ewma(0.5), &[100, 300, 300]);
+    assert_eq!(series, vec![100.0, 200.0, 250.0]);
+    assert_eq!(Smoothing::ewma_from_half_life(1.0), Smoothing::Ewma(0.5));
+}
+
+#[test]
+fn test_smoothing_none_reports_raw() {
+    let mut calc = BandwidthCalculator::new(Duration::from_secs(1), 100, 3);
+    calc.add_packet(test_packet(300, TrafficDirection::Inbound));
+    calc.calculate_bandwidth();
+    let bandwidth = calc.calculate_bandwidth();
+    assert_eq!(bandwidth.inbound, 150.0);
+    assert_eq!(bandwidth.raw_inbound, 0.0);
+
+    assert_eq!(smoothed_series(Smoothing::None, &[300, 100]), vec![300.0, 100.0]);
+}
+
+#[test]
+fn test_parse_interval() {
+    assert_eq!(parse_interval("2"), Ok(Duration::from_secs(2)));
+    assert_eq!(parse_interval("1.5s"), Ok(Duration::from_millis(1500)));
+    assert_eq!(parse_interval("250ms"), Ok(Duration::from_millis(250)));
+    assert_eq!(parse_interval("10m"), Ok(Duration::from_secs(600)));
+    assert_eq!(parse_interval("7d"), Ok(Duration::from_secs(7 * 86400)));
+    assert!(parse_interval("fast").is_err());
+    assert!(parse_interval("-1").is_err());
+}
+
+#[test]
+fn test_burst_peaks_from_capture_timestamps() {
+    let mut calc = BandwidthCalculator::new(Duration::from_secs(1), 100, 1);
+    let start = UNIX_EPOCH + Duration::from_secs(1_000);
+    // 3000 bytes inside one millisecond, 500 more later in the same 10 ms slot, then a quiet trickle
+    for (offset_us, size) in [(100, 1000), (400, 1000), (900, 1000), (5_000, 500), (20_000, 100), (500_000, 100)] {
+        calc.add_packet(PacketInfo {
+            timestamp: start + Duration::from_micros(offset_us),
+            ..test_packet(size, TrafficDirection::Inbound)
+        });
+    }
+
+    let bandwidth = calc.calculate_bandwidth();
+    assert_eq!(bandwidth.inbound, 3700.0);
+    assert_eq!(bandwidth.inbound_burst.peak_1ms, 3_000_000.0);
+    assert_eq!(bandwidth.inbound_burst.peak_10ms, 350_000.0);
+    assert_eq!(bandwidth.outbound_burst.peak_1ms, 0.0);
+
+    // Peaks belong to the interval they were seen in
+    let bandwidth = calc.calculate_bandwidth();
+    assert_eq!(bandwidth.inbound_burst.peak_1ms, 0.0);
+}
+
+fn assert_close(actual: f64, expected: f64) {
+    assert!((actual - expected).abs() <= expected * 0.01, "{} is not within 1% of {}", actual, expected);
+}
+
+#[test]
+fn test_rate_distribution_percentiles() {
+    let mut distribution = RateDistribution::default();
+    // 1..=100 kB/s, one interval each
+    for rate in 1..=100 {
+        distribution.record(rate as f64 * 1000.0);
+    }
+
+    let summary = distribution.summary();
+    assert_eq!(summary.samples, 100);
+    assert_eq!(summary.mean, 50_500.0);
+    assert_close(summary.p50, 50_000.0);
+    assert_close(summary.p90, 90_000.0);
+    assert_close(summary.p95, 95_000.0);
+    assert_close(summary.p99, 99_000.0);
+    assert_eq!(RateDistribution::default().summary(), RateSummary::default());
+}
+
+#[test]
+fn test_bandwidth_calculator_session_percentiles_ignore_smoothing() {
+    let mut calc = BandwidthCalculator::new(Duration::from_secs(1), 100, 5);
+    for _ in 0..19 {
+        calc.add_packet(test_packet(1000, TrafficDirection::Inbound));
+        calc.calculate_bandwidth();
+    }
+    // A single busy interval is the top 5% and does not move the p95
+    calc.add_packet(test_packet(100_000, TrafficDirection::Inbound));
+    let bandwidth = calc.calculate_bandwidth();
+
+    assert_eq!(bandwidth.inbound_summary.samples, 20);
+    assert_close(bandwidth.inbound_summary.p95, 1000.0);
+    assert_close(bandwidth.inbound_summary.p99, 100_000.0);
+    assert_close(bandwidth.inbound_summary.mean, 5950.0);
+    assert_eq!(bandwidth.outbound_summary.p95, 0.0);
+}
+
+#[test]
+fn test_bandwidth_calculator_session_percentiles_use_interval_rates() {
+    // A 5s window over 1s intervals would spread the busy interval over five averages
+    let mut calc = BandwidthCalculator::new(Duration::from_secs(5), 100, 1).with_update_interval(Duration::from_secs(1));
+    for _ in 0..19 {
+        calc.add_packet(test_packet(1000, TrafficDirection::Inbound));
+        calc.calculate_bandwidth();
+    }
+    calc.add_packet(test_packet(100_000, TrafficDirection::Inbound));
+    let bandwidth = calc.calculate_bandwidth();
+
+    assert_close(bandwidth.raw_inbound, 20_800.0);
+    assert_close(bandwidth.inbound_summary.p95, 1000.0);
+    assert_close(bandwidth.inbound_summary.p99, 100_000.0);
+}
+
+#[test]
+fn test_bandwidth_calculator_transfer_totals_and_reset() {
+    let mut calc = BandwidthCalculator::new(Duration::from_secs(1), 100, 1);
+    calc.add_packet(test_packet(1000, TrafficDirection::Inbound));
+    calc.add_packet(test_packet(1000, TrafficDirection::Inbound));
+    calc.add_packet(test_packet(300, TrafficDirection::Outbound));
+    calc.add_packet(test_packet(5000, TrafficDirection::Transit));
+    calc.calculate_bandwidth();
+
+    calc.reset_totals(SystemTime::now());
+    calc.add_packet(test_packet(700, TrafficDirection::Inbound));
+    let bandwidth = calc.calculate_bandwidth();
+
+    let session = bandwidth.session_totals;
+    assert_eq!((session.inbound_bytes, session.inbound_packets), (2700, 3));
+    assert_eq!((session.outbound_bytes, session.outbound_packets), (300, 1));
+
+    let since_reset = bandwidth.totals_since_reset;
+    assert_eq!((since_reset.inbound_bytes, since_reset.inbound_packets), (700, 1));
+    assert_eq!((since_reset.outbound_bytes, since_reset.outbound_packets), (0, 0));
+    assert!(since_reset.since >= session.since);
+}
+
+#[test]
+fn test_bandwidth_calculator_reset_splits_queued_packets() {
+    let mut calc = BandwidthCalculator::new(Duration::from_secs(1), 100, 1);
+    let (command_tx, command_rx) = std::sync::mpsc::channel();
+    let (packet_tx, packet_rx) = std::sync::mpsc::channel();
+    let pressed = SystemTime::now();
+
+    // Still queued when the key press arrives: one packet from before it, one from after
+    for (timestamp, size) in [(pressed - Duration::from_millis(10), 1000), (pressed + Duration::from_millis(10), 700)] {
+        packet_tx.send(PacketInfo { timestamp, ..test_packet(size, TrafficDirection::Inbound) }).unwrap();
+    }
+    command_tx.send(MonitorCommand::ResetTotals(pressed)).unwrap();
+
+    calc.drain(&command_rx, &packet_rx);
+    let bandwidth = calc.calculate_bandwidth();
+    assert_eq!(bandwidth.session_totals.inbound_bytes, 1700);
+    assert_eq!(bandwidth.totals_since_reset.inbound_bytes, 700);
+    assert_eq!(bandwidth.totals_since_reset.since, pressed);
+}
+
+#[test]
+fn test_parse_retention() {
+    assert_eq!(
+        parse_retention("10s:6h"),
+        Ok(RetentionTier { resolution: Duration::from_secs(10), span: Duration::from_secs(6 * 3600) })
+    );
+    assert_eq!(parse_retention("1m:7d").unwrap().capacity(), 10080);
+    assert!(parse_retention("10s").is_err());
+    assert!(parse_retention("1h:10m").is_err());
+}
+
+#[test]
+fn test_tiered_history_consolidates_min_avg_max() {
+    let mut history = TieredHistory::new(&[
+        RetentionTier { resolution: Duration::from_secs(1), span: Duration::from_secs(3) },
+        RetentionTier { resolution: Duration::from_secs(10), span: Duration::from_secs(60) },
+    ]);
+    let start = UNIX_EPOCH + Duration::from_secs(1_000_000);
+    for (second, inbound) in [100.0, 300.0, 200.0, 400.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1000.0].iter().enumerate() {
+        history.record(start + Duration::from_secs(second as u64), *inbound, 50.0);
+    }
+
+    // The finest tier keeps only its last 3 completed seconds
+    let fine = history.tiers()[0].points();
+    assert_eq!(fine.len(), 3);
+    assert_eq!(fine.back().unwrap().timestamp, start + Duration::from_secs(9));
+
+    // The first 10 s slot closed when the 11th sample arrived
+    let coarse = history.tiers()[1].points();
+    assert_eq!(coarse.len(), 1);
+    assert_eq!(coarse[0].timestamp, start);
+    assert_eq!(coarse[0].inbound, RateRange { min: 0.0, avg: 100.0, max: 400.0 });
+    assert_eq!(coarse[0].outbound, RateRange { min: 50.0, avg: 50.0, max: 50.0 });
+}
+
+#[test]
+fn test_parse_bytes() {
+    assert_eq!(parse_bytes("500GB"), Ok(500_000_000_000));
+    assert_eq!(parse_bytes("1.5 TB"), Ok(1_500_000_000_000));
+    assert_eq!(parse_bytes("20GiB"), Ok(20 * 1024 * 1024 * 1024));
+    assert_eq!(parse_bytes("4096"), Ok(4096));
+    assert!(parse_bytes("5 parsecs").is_err());
+    assert!(parse_bytes("0").is_err());
+}
+
+// 2026-10-18 12:00:00 UTC, a Sunday
+fn quota_now() -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(1_792_324_800)
+}
+
+#[test]
+fn test_quota_period_bounds() {
+    let now = quota_now();
+    assert_eq!(format_utc(now), "2026-10-18 12:00 UTC");
+
+    let (start, end) = QuotaPeriod::Day.bounds(now, 1);
+    assert_eq!((format_utc(start), format_utc(end)), ("2026-10-18 00:00 UTC".into(), "2026-10-19 00:00 UTC".into()));
+
+    let (start, end) = QuotaPeriod::Week.bounds(now, 1);
+    assert_eq!((format_utc(start), format_utc(end)), ("2026-10-12 00:00 UTC".into(), "2026-10-19 00:00 UTC".into()));
+
+    let (start, end) = QuotaPeriod::Month.bounds(now, 1);
+    assert_eq!((format_utc(start), format_utc(end)), ("2026-10-01 00:00 UTC".into(), "2026-11-01 00:00 UTC".into()));
+
+    // A billing cycle starting on the 20th is still in the cycle that began in September
+    let (start, end) = QuotaPeriod::Month.bounds(now, 20);
+    assert_eq!((format_utc(start), format_utc(end)), ("2026-09-20 00:00 UTC".into(), "2026-10-20 00:00 UTC".into()));
+
+    let new_year = UNIX_EPOCH + Duration::from_secs(1_798_761_600 + 3600); // 2027-01-01 01:00 UTC
+    let (start, end) = QuotaPeriod::Month.bounds(new_year, 15);
+    assert_eq!((format_utc(start), format_utc(end)), ("2026-12-15 00:00 UTC".into(), "2027-01-15 00:00 UTC".into()));
+}
+
+fn quota_config(name: &str) -> QuotaConfig {
+    let state_file = std::env::temp_dir().join(format!("tcpgraph-test-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_file(&state_file);
+    QuotaConfig {
+        limit_bytes: 1_000_000,
+        period: QuotaPeriod::Month,
+        direction: QuotaDirection::Both,
+        start_day: 1,
+        warn_percents: vec![50.0, 80.0],
+        state_file,
+    }
+}
+
+#[test]
+fn test_quota_tracker_persists_usage_within_period() {
+    let config = quota_config("persist");
+    let now = quota_now();
+
+    let mut tracker = QuotaTracker::load(config.clone(), now).unwrap();
+    tracker.add(300_000, 250_000, now);
+
+    // Reloading in the same period picks the usage up again
+    let restored = QuotaTracker::load(config.clone(), now + Duration::from_secs(60)).unwrap();
+    let status = restored.status(now + Duration::from_secs(60));
+    assert_eq!(status.used_bytes, 550_000);
+    assert_eq!(status.remaining_bytes(), 450_000);
+    assert_eq!(status.warning, Some(50.0));
+    assert_eq!(status.save_error, None);
+
+    // A restart in the next month starts from zero
+    let next_month = UNIX_EPOCH + Duration::from_secs(1_793_577_600); // 2026-11-02 00:00 UTC
+    let restored = QuotaTracker::load(config.clone(), next_month).unwrap();
+    assert_eq!(restored.status(next_month).used_bytes, 0);
+
+    std::fs::remove_file(&config.state_file).unwrap();
+}
+
+#[test]
+fn test_quota_temp_file_keeps_the_extension() {
+    // A VLAN interface name puts a dot in the state file name
+    let mut config = quota_config("vlan");
+    config.state_file.set_extension("100");
+    let other_temp = config.state_file.with_extension("tmp");
+    std::fs::write(&other_temp, "not ours").unwrap();
+
+    let mut tracker = QuotaTracker::load(config.clone(), quota_now()).unwrap();
+    tracker.add(1000, 0, quota_now());
+
+    assert_eq!(QuotaTracker::load(config.clone(), quota_now()).unwrap().status(quota_now()).used_bytes, 1000);
+    assert_eq!(std::fs::read_to_string(&other_temp).unwrap(), "not ours");
+
+    std::fs::remove_file(&config.state_file).unwrap();
+    std::fs::remove_file(&other_temp).unwrap();
+}
+
+#[test]
+fn test_bandwidth_calculator_saves_quota_on_finish() {
+    let config = quota_config("finish");
+    let quota = QuotaTracker::load(config.clone(), SystemTime::now()).unwrap();
+    let mut calc = BandwidthCalculator::new(Duration::from_secs(1), 100, 1).with_quota(quota);
+
+    // The first interval is saved right away, the next only after a few seconds
+    calc.add_packet(test_packet(1000, TrafficDirection::Inbound));
+    calc.calculate_bandwidth();
+    calc.add_packet(test_packet(500, TrafficDirection::Outbound));
+    calc.calculate_bandwidth();
+    // Not even closed into an interval yet
+    calc.add_packet(test_packet(200, TrafficDirection::Inbound));
+
+    let saved = || QuotaTracker::load(config.clone(), SystemTime::now()).unwrap().status(SystemTime::now()).used_bytes;
+    assert_eq!(saved(), 1000);
+    calc.finish().unwrap();
+    assert_eq!(saved(), 1700);
+
+    std::fs::remove_file(&config.state_file).unwrap();
+}
+
+#[test]
+fn test_quota_tracker_projection_and_rollover() {
+    let mut config = quota_config("projection");
+    config.direction = QuotaDirection::Inbound;
+    let now = quota_now();
+
+    let mut tracker = QuotaTracker::load(config.clone(), now).unwrap();
+    // 17.5 days into October with 900 kB in: the rest lasts about two days
+    tracker.add(900_000, 5_000_000, now);
+    let status = tracker.status(now);
+    assert_eq!(status.used_bytes, 900_000);
+    assert_eq!(status.warning, Some(80.0));
+    assert_eq!(format_utc(status.projected_exhaustion.unwrap()), "2026-10-20 10:40 UTC");
+
+    // Crossing into November resets the counters
+    let next_month = UNIX_EPOCH + Duration::from_secs(1_793_577_600);
+    tracker.add(1000, 0, next_month);
+    let status = tracker.status(next_month);
+    assert_eq!(status.used_bytes, 1000);
+    assert_eq!(status.warning, None);
+
+    std::fs::remove_file(&config.state_file).unwrap();
+}
+
+#[test]
+fn test_anomaly_baselines() {
+    let mean = BaselineMethod::Mean.baseline(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]).unwrap();
+    assert_eq!(mean, Baseline { center: 5.0, sigma: 2.0 });
+
+    // The median and MAD barely move for a single earlier spike
+    let mad = BaselineMethod::Mad.baseline(&[1.0, 2.0, 3.0, 4.0, 100.0]).unwrap();
+    assert_eq!(mad.center, 3.0);
+    assert!((mad.sigma - 1.4826).abs() < 1e-9);
+
+    assert_eq!(BaselineMethod::Off.baseline(&[1.0, 2.0]), None);
+}
+
+#[test]
+fn test_anomaly_detector_flags_deviations() {
+    let mut detector = AnomalyDetector::new(BaselineMethod::Mad, 4.0);
+    let now = SystemTime::now();
+    let history: Vec<f64> = (0..40).map(|i| 1_000_000.0 + (i % 5) as f64 * 10_000.0).collect();
+
+    // Not enough history yet to call anything unusual
+    assert_eq!(detector.check(now, TrafficDirection::Inbound, &history[..10], 5_000_000.0), None);
+
+    assert_eq!(detector.check(now, TrafficDirection::Inbound, &history, 1_030_000.0), None);
+
+    let spike = detector.check(now, TrafficDirection::Inbound, &history, 5_000_000.0).unwrap();
+    assert_eq!(spike.direction, TrafficDirection::Inbound);
+    assert_eq!(spike.baseline, 1_020_000.0);
+    assert!(spike.sigmas > 4.0);
+
+    let drop = detector.check(now, TrafficDirection::Outbound, &history, 0.0).unwrap();
+    assert!(drop.sigmas < -4.0);
+}
+
+#[test]
+fn test_bandwidth_calculator_reports_anomalies() {
+    let mut calc = BandwidthCalculator::new(Duration::from_secs(1), 100, 1)
+        .with_anomaly_detector(AnomalyDetector::new(BaselineMethod::Mad, 4.0));
+
+    for _ in 0..40 {
+        calc.add_packet(test_packet(100_000, TrafficDirection::Inbound));
+        calc.add_packet(test_packet(100_000, TrafficDirection::Outbound));
+        assert!(calc.calculate_bandwidth().anomalies.is_empty());
+    }
+
+    calc.add_packet(test_packet(5_000_000, TrafficDirection::Inbound));
+    calc.add_packet(test_packet(100_000, TrafficDirection::Outbound));
+    let bandwidth = calc.calculate_bandwidth();
+
+    assert_eq!(bandwidth.anomalies.len(), 1);
+    assert_eq!(bandwidth.anomalies[0].direction, TrafficDirection::Inbound);
+    assert_eq!(bandwidth.anomalies[0].rate, 5_000_000.0);
+    assert_eq!(bandwidth.anomalies[0].baseline, 100_000.0);
+}
+
+#[test]
+fn test_parse_alert() {
+    let rule = parse_alert("in > 800Mbps for 10s").unwrap();
+    assert_eq!(rule.text, "in > 800Mbps for 10s");
+    assert_eq!(rule.metric, AlertMetric::Inbound);
+    assert_eq!(rule.comparison, Comparison::Above);
+    assert_eq!(rule.threshold, 100_000_000.0);
+    assert_eq!(rule.duration, Duration::from_secs(10));
+
+    let rule = parse_alert("out_pps<2kpps").unwrap();
+    assert_eq!(rule.metric, AlertMetric::OutboundPps);
+    assert_eq!(rule.comparison, Comparison::Below);
+    assert_eq!(rule.threshold, 2000.0);
+    assert_eq!(rule.duration, Duration::ZERO);
+
+    assert!(parse_alert("in = 1Mbps").is_err());
+    assert!(parse_alert("sideways > 1Mbps").is_err());
+    assert!(parse_alert("in > 1MB").is_err());
+    assert!(parse_alert("in > 1Mbps for ever").is_err());
+}
+
+#[test]
+fn test_alert_engine_duration_and_hysteresis() {
+    let mut engine = AlertEngine::new(vec![parse_alert("in > 80Mbps for 10s").unwrap()], 10.0);
+    let start = UNIX_EPOCH + Duration::from_secs(1_000_000);
+    let at = |secs: u64| start + Duration::from_secs(secs);
+    let mbps = |rate: f64| move |_: AlertMetric| rate * 1_000_000.0 / 8.0;
+
+    // A short spike doesn't fire
+    assert!(engine.evaluate(at(0), Duration::from_secs(1), mbps(90.0)).events.is_empty());
+    assert!(engine.evaluate(at(5), Duration::from_secs(1), mbps(50.0)).events.is_empty());
+
+    // Held for 10 s it does
+    assert!(engine.evaluate(at(6), Duration::from_secs(1), mbps(90.0)).events.is_empty());
+    let status = engine.evaluate(at(16), Duration::from_secs(1), mbps(95.0));
+    assert_eq!(status.events.len(), 1);
+    assert_eq!(status.events[0].state, AlertState::Firing);
+    assert_eq!(status.firing, vec!["in > 80Mbps for 10s".to_string()]);
+
+    // Just under the threshold is within the hysteresis band and keeps it firing
+    assert!(engine.evaluate(at(17), Duration::from_secs(1), mbps(75.0)).events.is_empty());
+    assert!(engine.evaluate(at(30), Duration::from_secs(1), mbps(75.0)).events.is_empty());
+
+    // Below 72 Mbps (10% under) for 10 s clears it
+    assert!(engine.evaluate(at(31), Duration::from_secs(1), mbps(70.0)).events.is_empty());
+    let status = engine.evaluate(at(41), Duration::from_secs(1), mbps(70.0));
+    assert_eq!(status.events.len(), 1);
+    assert_eq!(status.events[0].state, AlertState::Cleared);
+    assert!(status.firing.is_empty());
+}
+
+#[test]
+fn test_alert_engine_duration_counts_from_interval_start() {
+    let mut engine = AlertEngine::new(vec![parse_alert("in > 80Mbps for 10s").unwrap()], 10.0);
+    let start = UNIX_EPOCH + Duration::from_secs(1_000_000);
+    let over = |_: AlertMetric| 90.0 * 1_000_000.0 / 8.0;
+
+    // The breach starts with the first interval, so ten 1 s intervals are exactly 10 s
+    for secs in 1..10 {
+        assert!(engine.evaluate(start + Duration::from_secs(secs), Duration::from_secs(1), over).events.is_empty());
+    }
+    let status = engine.evaluate(start + Duration::from_secs(10), Duration::from_secs(1), over);
+    assert_eq!(status.events.len(), 1);
+    assert_eq!(status.events[0].state, AlertState::Firing);
+}
+
+#[test]
+fn test_alert_engine_runs_hook_command() {
+    let output = std::env::temp_dir().join(format!("tcpgraph-test-alert-{}", std::process::id()));
+    let command = format!(
+        "echo \"$TCPGRAPH_ALERT_STATE $TCPGRAPH_ALERT_METRIC $TCPGRAPH_ALERT_VALUE $TCPGRAPH_ALERT_THRESHOLD $TCPGRAPH_ALERT_UNIT $TCPGRAPH_ALERT_INTERFACE\" > {}",
+        output.display()
+    );
+    let mut engine = AlertEngine::new(vec![parse_alert("out > 1Mbps").unwrap()], 10.0)
+        .with_command(command, "eth0".to_string());
+
+    let status = engine.evaluate(SystemTime::now(), Duration::from_secs(1), |_| 250_000.0);
+    assert_eq!(status.events.len(), 1);
+    assert_eq!(status.hook_error, None);
+
+    // The hook runs in the background
+    let mut contents = String::new();
+    for _ in 0..100 {
+        contents = std::fs::read_to_string(&output).unwrap_or_default();
+        if contents.ends_with('\n') {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    assert_eq!(contents, "firing out 2000000 1000000 bps eth0\n");
+
+    std::fs::remove_file(&output).unwrap();
+}
+
+#[test]
+fn test_parse_series() {
+    let series = parse_series("https=tcp port 443").unwrap();
+    assert_eq!(series.name, "https");
+    assert_eq!(series.filter, "tcp port 443");
+
+    // Only the first '=' separates the name
+    assert_eq!(parse_series("db=tcp port 5432 or ip[8]=64").unwrap().filter, "tcp port 5432 or ip[8]=64");
+
+    assert!(parse_series("tcp port 443").is_err());
+    assert!(parse_series("=tcp").is_err());
+    assert!(parse_series("web=").is_err());
+}
+
+#[test]
+fn test_bandwidth_calculator_series_rates() {
+    let mut calc = BandwidthCalculator::new(Duration::from_secs(1), 100, 1).with_series(2);
+
+    let tagged = |size, direction, series| PacketInfo { series, ..test_packet(size, direction) };
+    calc.add_packet(tagged(1000, TrafficDirection::Inbound, 0b01));
+    calc.add_packet(tagged(500, TrafficDirection::Outbound, 0b11));
+    calc.add_packet(tagged(200, TrafficDirection::Transit, 0b10));
+    calc.add_packet(tagged(300, TrafficDirection::Inbound, 0));
+    let bandwidth = calc.calculate_bandwidth();
+
+    // A packet counts towards every series it matched, whatever its direction
+    assert_eq!(bandwidth.series, vec![1500.0, 700.0]);
+    assert_eq!(bandwidth.inbound, 1300.0);
+
+    let bandwidth = calc.calculate_bandwidth();
+    assert_eq!(bandwidth.series, vec![0.0, 0.0]);
+}
+
+#[test]
+fn test_bandwidth_calculator_window_averages() {
+    let mut calc = BandwidthCalculator::new(Duration::from_secs(1), 100, 1);
+
+    calc.add_packet(test_packet(1000, TrafficDirection::Inbound));
+    let bandwidth = calc.calculate_bandwidth();
+    // Windows that haven't filled up yet average over what has been seen
+    assert_eq!(bandwidth.averages.len(), AVERAGE_WINDOWS.len());
+    assert!(bandwidth.averages.iter().all(|average| average.inbound == 1000.0));
+
+    for _ in 1..20 {
+        calc.add_packet(test_packet(1000, TrafficDirection::Inbound));
+        calc.calculate_bandwidth();
+    }
+    let mut bandwidth = calc.calculate_bandwidth();
+    for _ in 0..10 {
+        calc.add_packet(test_packet(4000, TrafficDirection::Inbound));
+        calc.add_packet(test_packet(500, TrafficDirection::Outbound));
+        bandwidth = calc.calculate_bandwidth();
+    }
+
+    // 10 s: only the 4000 B intervals; 1 min and 5 min: 20 x 1000 + 1 x 0 + 10 x 4000 over 31 s
+    assert_eq!(bandwidth.averages[0].window, Duration::from_secs(10));
+    assert_eq!(bandwidth.averages[0].inbound, 4000.0);
+    assert_eq!(bandwidth.averages[0].outbound, 500.0);
+    assert_eq!(bandwidth.averages[1].inbound, 60_000.0 / 31.0);
+    assert_eq!(bandwidth.averages[2].inbound, 60_000.0 / 31.0);
+    assert_eq!(bandwidth.averages[2].outbound, 5000.0 / 31.0);
+}
+
+#[test]
+fn test_window_averages_follow_update_interval() {
+    let mut calc = BandwidthCalculator::new(Duration::from_millis(500), 100, 1)
+        .with_update_interval(Duration::from_millis(500));
+
+    // 30 intervals of 500 ms: the 10 s window holds the last 20 of them
+    for index in 0..30 {
+        calc.add_packet(test_packet(if index < 10 { 100 } else { 1000 }, TrafficDirection::Inbound));
+        calc.calculate_bandwidth();
+    }
+    calc.add_packet(test_packet(1000, TrafficDirection::Inbound));
+    let bandwidth = calc.calculate_bandwidth();
+
+    assert_eq!(bandwidth.averages[0].inbound, 2000.0);
+    assert_eq!(bandwidth.averages[1].inbound, (10.0 * 100.0 + 21.0 * 1000.0) / 15.5);
+}
+
+#[test]
+fn test_window_averages_rounded_to_whole_intervals() {
+    let calc = |update_interval| BandwidthCalculator::new(update_interval, 100, 1).with_update_interval(update_interval);
+    let windows = |update_interval| -> Vec<Duration> {
+        calc(update_interval).calculate_bandwidth().averages.iter().map(|average| average.window).collect()
+    };
+
+    // No 10 s average out of 45 s intervals; 1 min is one interval, 5 min seven of them
+    assert_eq!(windows(Duration::from_secs(45)), vec![Duration::from_secs(45), Duration::from_secs(315)]);
+    assert_eq!(windows(Duration::from_secs(1)), AVERAGE_WINDOWS.to_vec());
+}
+
+#[test]
+fn test_parse_rate() {
+    assert_eq!(parse_rate("1Gbps"), Ok(125_000_000.0));
+    assert_eq!(parse_rate("2.5 mbps"), Ok(312_500.0));
+    assert_eq!(parse_rate("8000"), Ok(1000.0));
+    assert_eq!(parse_rate("10G"), Ok(1_250_000_000.0));
+    assert_eq!(parse_rate("100m"), Ok(12_500_000.0));
+    assert!(parse_rate("1GB").is_err());
+}
+
+#[test]
+fn test_forecast_follows_linear_ramp() {
+    // Too short to have a trend
+    assert_eq!(Forecast::fit((0..5).map(|i| i as f64)), None);
+
+    let forecast = Forecast::fit((0..200).map(|i| 1_000_000.0 + i as f64 * 10_000.0)).unwrap();
+    assert!((forecast.level - 2_990_000.0).abs() < 1_000.0, "level {}", forecast.level);
+    assert!((forecast.trend - 10_000.0).abs() < 100.0, "trend {}", forecast.trend);
+    assert!((forecast.project(forecast.level, 100.0) - 3_990_000.0).abs() < 20_000.0);
+
+    // About 100 intervals of 1 s to go from ~3 MB/s to 4 MB/s
+    let eta = forecast.time_to_reach(4_000_000.0, Duration::from_secs(1)).unwrap();
+    assert!((eta.as_secs_f64() - 101.0).abs() < 5.0, "eta {:?}", eta);
+    assert_eq!(forecast.time_to_reach(1_000_000.0, Duration::from_secs(1)), Some(Duration::ZERO));
+
+    // A falling rate never reaches a higher one, and the projection stops at zero
+    let falling = Forecast::fit((0..50).map(|i| 500_000.0 - i as f64 * 10_000.0)).unwrap();
+    assert_eq!(falling.time_to_reach(1_000_000.0, Duration::from_secs(1)), None);
+    assert_eq!(falling.project(falling.level, 1000.0), 0.0);
+}
+
+#[test]
+fn test_bandwidth_calculator_forecast() {
+    let mut calc = BandwidthCalculator::new(Duration::from_secs(1), 100, 1);
+    calc.add_packet(test_packet(1000, TrafficDirection::Inbound));
+    assert_eq!(calc.calculate_bandwidth().inbound_forecast, None);
+
+    let mut calc = calc.with_forecast();
+    let mut bandwidth = calc.calculate_bandwidth();
+    for size in (1..30).map(|i| i * 1000) {
+        calc.add_packet(test_packet(size, TrafficDirection::Inbound));
+        bandwidth = calc.calculate_bandwidth();
+    }
+
+    assert!(bandwidth.inbound_forecast.unwrap().trend > 500.0);
+    assert_eq!(bandwidth.outbound_forecast.unwrap().trend, 0.0);
+}
+
+#[test]
+fn test_parse_link_speed() {
+    assert_eq!(PacketCapture::parse_link_speed("1000\n"), Some(125_000_000.0));
+    assert_eq!(PacketCapture::parse_link_speed("25000"), Some(3_125_000_000.0));
+    // Unknown speed (Wi-Fi, link down) or garbage
+    assert_eq!(PacketCapture::parse_link_speed("-1\n"), None);
+    assert_eq!(PacketCapture::parse_link_speed(""), None);
+    assert_eq!(PacketCapture::link_speed("no-such-interface"), None);
+}
+
+const PROC_NET_DEV: &str = "\
+Inter-|   Receive                                                |  Transmit
+ face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed
+    lo:  123456     100    0    0    0     0          0         0   123456     100    0    0    0     0       0          0
+  eth0: 9000000    6000    0    7    0     0          0        12  3000000    2000    0    1    0     0       0          0
+";
+
+#[test]
+fn test_parse_proc_net_dev() {
+    let counters = InterfaceCounters::parse(PROC_NET_DEV, "eth0").unwrap();
+    assert_eq!(
+        counters,
+        InterfaceCounters {
+            rx_bytes: 9_000_000,
+            rx_packets: 6000,
+            rx_dropped: 7,
+            tx_bytes: 3_000_000,
+            tx_packets: 2000,
+            tx_dropped: 1,
+        }
+    );
+    assert_eq!(InterfaceCounters::parse(PROC_NET_DEV, "lo").unwrap().rx_packets, 100);
+    assert_eq!(InterfaceCounters::parse(PROC_NET_DEV, "eth1"), None);
+    assert_eq!(InterfaceCounters::parse(PROC_NET_DEV, "face"), None);
+    assert!(CounterSampler::for_interface("no-such-interface").is_none());
+}
+
+#[test]
+fn test_counter_sampler_comparison() {
+    let start = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let base = InterfaceCounters::parse(PROC_NET_DEV, "eth0").unwrap();
+    let mut sampler = CounterSampler::new("eth0".to_string());
+
+    // The first sample is only the baseline, whatever was captured before it
+    assert_eq!(sampler.observe(start, base, 60), None);
+
+    let later = InterfaceCounters {
+        rx_bytes: base.rx_bytes + 2_000_000,
+        rx_packets: base.rx_packets + 1000,
+        rx_dropped: base.rx_dropped + 3,
+        tx_bytes: base.tx_bytes + 500_000,
+        tx_packets: base.tx_packets + 400,
+        ..base
+    };
+    // 1400 packets went through the interface, the capture got 700 of them in any direction
+    let comparison = sampler.observe(start + Duration::from_secs(2), later, 60 + 700).unwrap();
+    assert_eq!(comparison.inbound, 1_000_000.0);
+    assert_eq!(comparison.outbound, 250_000.0);
+    assert_eq!(comparison.matched, Some(50.0));
+    assert_eq!((comparison.rx_dropped, comparison.tx_dropped), (3, 0));
+
+    // An idle interval keeps the cumulative match, with no kernel traffic to compare
+    let idle = sampler.observe(start + Duration::from_secs(3), later, 760).unwrap();
+    assert_eq!((idle.inbound, idle.outbound), (0.0, 0.0));
+    assert_eq!(idle.matched, Some(50.0));
+}
+
+#[test]
+fn test_bandwidth_calculator_reports_capture_drops() {
+    let drops = Arc::new(CaptureDrops::default());
+    let mut calc = BandwidthCalculator::new(Duration::from_secs(1), 100, 1);
+    assert_eq!(calc.calculate_bandwidth().capture_drops, None);
+
+    let mut calc = calc.with_capture_drops(Arc::clone(&drops));
+    drops.update(CaptureDropCounts { dropped: 12, interface_dropped: 0 });
+    assert_eq!(calc.calculate_bandwidth().capture_drops, Some(CaptureDropCounts { dropped: 12, interface_dropped: 0 }));
+}
+
+#[test]
+fn test_counters_for_any_interface() {
+    let total = InterfaceCounters::parse_total(PROC_NET_DEV, "any", |_| false).unwrap();
+    // Loopback is left out
+    assert_eq!((total.rx_bytes, total.tx_packets), (9_000_000, 2000));
+
+    let extra = format!("{}  wlan0:    1000      10    0    0    0     0          0         0     500       5    0    0    0     0       0          0\n", PROC_NET_DEV);
+    let total = InterfaceCounters::parse_total(&extra, "any", |_| false).unwrap();
+    assert_eq!((total.rx_bytes, total.rx_packets, total.tx_bytes), (9_001_000, 6010, 3_000_500));
+    assert_eq!(InterfaceCounters::parse_total(&extra, "wlan0", |_| false).unwrap().rx_bytes, 1000);
+
+    // A bridge port's traffic is already in the bridge's counters
+    let total = InterfaceCounters::parse_total(&extra, "any", |name| name == "wlan0").unwrap();
+    assert_eq!(total.rx_bytes, 9_000_000);
+    assert!(!InterfaceCounters::has_master("no-such-interface"));
+    assert!(CounterSource::open("no-such-interface").is_err());
+}
+
+#[test]
+fn test_counter_source_drives_calculator() {
+    let base = InterfaceCounters::parse(PROC_NET_DEV, "eth0").unwrap();
+    let mut source = CounterSource::new("eth0".to_string(), base);
+    let delta = source.observe(InterfaceCounters {
+        rx_bytes: base.rx_bytes + 250_000,
+        rx_packets: base.rx_packets + 200,
+        tx_bytes: base.tx_bytes + 50_000,
+        tx_packets: base.tx_packets + 100,
+        ..base
+    });
+    assert_eq!((delta.rx_bytes, delta.rx_packets, delta.tx_bytes, delta.tx_packets), (250_000, 200, 50_000, 100));
+
+    // A counter that went backwards (the interface was reset) counts as no traffic
+    assert_eq!(source.observe(base), InterfaceCounters::default());
+
+    let mut calc = BandwidthCalculator::new(Duration::from_secs(1), 10, 1);
+    calc.add_counters(&delta);
+    let bandwidth = calc.calculate_bandwidth();
+    assert_eq!(bandwidth.inbound, 250_000.0);
+    assert_eq!(bandwidth.outbound, 50_000.0);
+    assert_eq!(bandwidth.inbound_pps, 200.0);
+    assert_eq!(bandwidth.session_totals.outbound_packets, 100);
+    assert_eq!(bandwidth.inbound_goodput, 0.0);
+    // Each frame also takes 24 bytes of FCS, preamble and gap on the wire
+    assert_eq!(bandwidth.inbound_wire, 254_800.0);
+}
+
+#[test]
+fn test_parse_format() {
+    let format = parse_format("{time} in={in:8} {{raw}}").unwrap();
+    assert_eq!(
+        format.pieces,
+        vec![
+            FormatPiece::Field(OutputField::Time, 0),
+            FormatPiece::Text(" in=".to_string()),
+            FormatPiece::Field(OutputField::Inbound, 8),
+            FormatPiece::Text(" {raw}".to_string()),
+        ]
+    );
+    assert_eq!(format.header(), "time in=      in {raw}");
+    assert!(parse_format(DEFAULT_FORMAT).is_ok());
+
+    assert!(parse_format("{inbound}").is_err());
+    assert!(parse_format("{in:wide}").is_err());
+    assert!(parse_format("{in").is_err());
+    assert!(parse_format("in}").is_err());
+}
+
+#[test]
+fn test_format_renders_interval() {
+    let mut calc = BandwidthCalculator::new(Duration::from_secs(1), 10, 1);
+    calc.add_packet(test_packet(125_000, TrafficDirection::Inbound));
+    calc.add_packet(test_packet(1000, TrafficDirection::Outbound));
+    let mut bandwidth = calc.calculate_bandwidth();
+    bandwidth.timestamp = UNIX_EPOCH + Duration::from_secs(1_792_314_847);
+
+    let format = parse_format("{timestamp},{time},{in},{out:6},{in_bps},{out_pps},{in_bytes},{out_packets}").unwrap();
+    assert_eq!(
+        format.render(&bandwidth),
+        "1792314847,2026-10-18T09:14:07Z,1.00,  0.01,1000000,1,125000,1"
+    );
+    assert_eq!(format_rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+}